@@ -1,13 +1,37 @@
 // [[file:../gchemol-geometry.note::040d137b][040d137b]]
 use super::*;
+use crate::prelude::*;
 use vecfx::*;
 
 type Point3 = [f64; 3];
 // 040d137b ends here
 
 // [[file:../gchemol-geometry.note::*mods][mods:1]]
+mod constrained;
+mod ensemble;
+mod error;
+mod gradient;
+mod hungarian;
+mod icp;
+mod kabsch;
+mod pairwise;
+mod periodic;
+mod permutation;
+mod prepared;
 mod qcprot;
 mod quaternion;
+mod report;
+mod robust;
+mod scaling;
+mod selection;
+
+pub use self::ensemble::*;
+pub use self::error::SuperposeError;
+pub use self::pairwise::*;
+pub use self::periodic::PeriodicSuperposition;
+pub use self::prepared::PreparedReference;
+pub use self::report::DeviationReport;
+pub use self::robust::CoreSelection;
 // mods:1 ends here
 
 // [[file:../gchemol-geometry.note::*base][base:1]]
@@ -15,6 +39,8 @@ mod quaternion;
 pub enum SuperpositionAlgo {
     QCP,
     Quaternion,
+    /// SVD-based Kabsch algorithm
+    Kabsch,
 }
 
 impl Default for SuperpositionAlgo {
@@ -24,6 +50,34 @@ impl Default for SuperpositionAlgo {
     }
 }
 
+impl SuperpositionAlgo {
+    /// Superpose `candidate` onto `reference` using a proper rotation.
+    pub(crate) fn superimpose(self, reference: &[Point3], candidate: &[Point3], weights: Option<&[f64]>) -> Superposition {
+        // calculate the RMSD & rotational matrix
+        let (rmsd, trans, rot) = match self {
+            SuperpositionAlgo::QCP => self::qcprot::calc_rmsd_rotational_matrix(reference, candidate, weights),
+            SuperpositionAlgo::Quaternion => self::quaternion::calc_rmsd_rotational_matrix(reference, candidate, weights),
+            SuperpositionAlgo::Kabsch => self::kabsch::calc_rmsd_rotational_matrix(reference, candidate, weights),
+        };
+
+        // return unit matrix if two structures are already close enough
+        let rotation_matrix = if let Some(rot) = rot {
+            Matrix3f::from_row_slice(&rot)
+        } else {
+            Matrix3f::identity()
+        };
+
+        // return superimposition result
+        Superposition {
+            rmsd,
+            translation: trans.into(),
+            rotation_matrix,
+            reflection: false,
+            scale: 1.0,
+        }
+    }
+}
+
 /// The result of alignment defining how to superimpose.
 #[derive(Clone, Debug)]
 pub struct Superposition {
@@ -35,15 +89,41 @@ pub struct Superposition {
 
     /// rotation matrix
     pub rotation_matrix: Matrix3f,
+
+    /// true if `rotation_matrix` is an improper rotation (rotation-reflection)
+    pub reflection: bool,
+
+    /// isotropic scale factor applied before translation, which is 1.0
+    /// unless uniform scaling is allowed in superposition
+    pub scale: f64,
 }
 
 impl Superposition {
+    /// Return the rigid-body transformation defining the superposition. An
+    /// error is returned if the scale factor is not 1.0, which cannot be
+    /// represented by a rigid-body transformation.
+    ///
+    /// This is the intended accessor for the transformation found by
+    /// `Superpose::onto`, which can be composed, inverted or exported as a
+    /// 4x4 homogeneous matrix, instead of reading `rotation_matrix` and
+    /// `translation` directly.
+    pub fn transform(&self) -> Result<RigidTransform> {
+        if self.scale != 1.0 {
+            bail!("not a rigid-body superposition: scale = {}", self.scale);
+        }
+        Ok(RigidTransform::new(self.rotation_matrix, self.translation))
+    }
+
     /// Apply superposition to other structure `conf`.
+    ///
+    /// # Panics
+    ///
+    /// * panics if NaN floats found in the result. See also `try_apply`.
     pub fn apply(&self, conf: &[[f64; 3]]) -> Vec<[f64; 3]> {
         let mut res = Vec::with_capacity(conf.len());
         for &v in conf {
             let v = Vector3f::from(v);
-            let v = self.rotation_matrix * v + self.translation;
+            let v = self.scale * (self.rotation_matrix * v) + self.translation;
             res.push(v.into());
         }
 
@@ -68,18 +148,65 @@ impl Superposition {
         res
     }
 
-    /// Apply rotation to other structure `conf`.
+    /// Apply rotation to other structure `conf`. The scale factor is not
+    /// applied, so `apply` is the same as `apply_rotation` followed by
+    /// `apply_translation` only if the scale factor is 1.0.
     pub fn apply_rotation(&self, conf: &[Point3]) -> Vec<Point3> {
         let mut res = Vec::with_capacity(conf.len());
         for &v in conf {
             let v = Vector3f::from(v);
-            let v = v + self.rotation_matrix * v;
+            let v = self.rotation_matrix * v;
             res.push(v.into());
         }
 
         res
     }
 }
+
+impl TryFrom<&Superposition> for RigidTransform {
+    type Error = Error;
+
+    /// See `Superposition::transform`.
+    fn try_from(sp: &Superposition) -> Result<Self> {
+        sp.transform()
+    }
+}
+
+/// Superpose `candidate` onto `reference` using `fit` for the proper
+/// rotation. The mirror image of `candidate` is also tried if
+/// `allow_reflection`, and the scale factor is solved if `allow_scaling`.
+fn superimpose_with(
+    candidate: &[Point3],
+    reference: &[Point3],
+    weights: Option<&[f64]>,
+    allow_reflection: bool,
+    allow_scaling: bool,
+    fit: impl Fn(&[Point3]) -> Superposition,
+) -> Superposition {
+    let sp = fit(candidate);
+    let mut sp = if !allow_reflection {
+        sp
+    } else {
+        // superpose the mirror image of the candidate, and keep the better one
+        let mut mirrored = candidate.to_vec();
+        mirrored.mirror_invert();
+        let mut sp_mirrored = fit(&mirrored);
+        if sp_mirrored.rmsd < sp.rmsd {
+            // mirror inversion along z-axis followed by the proper rotation
+            let mat_m = Matrix3f::from_diagonal(&Vector3f::new(1.0, 1.0, -1.0));
+            sp_mirrored.rotation_matrix *= mat_m;
+            sp_mirrored.reflection = true;
+            sp_mirrored
+        } else {
+            sp
+        }
+    };
+
+    if allow_scaling {
+        sp.rescale(candidate, reference, weights);
+    }
+    sp
+}
 // base:1 ends here
 
 // [[file:../gchemol-geometry.note::*alignment/deprecated][alignment/deprecated:1]]
@@ -136,32 +263,15 @@ impl<'a> Alignment<'a> {
     }
 
     /// Superpose candidate structure onto reference structure which will be held fixed
-    /// Return superposition struct
+    /// Return superposition struct, whose rigid-body transformation is
+    /// available from `Superposition::transform`.
     ///
     /// Parameters
     /// ----------
     /// * reference: reference coordinates
     /// * weights  : weight of each point
     pub fn superpose(&mut self, reference: &[[f64; 3]], weights: Option<&[f64]>) -> Result<Superposition> {
-        // calculate the RMSD & rotational matrix
-        let (rmsd, trans, rot) = match self.algorithm {
-            SuperpositionAlgo::QCP => self::qcprot::calc_rmsd_rotational_matrix(&reference, &self.positions, weights),
-            SuperpositionAlgo::Quaternion => self::quaternion::calc_rmsd_rotational_matrix(&reference, &self.positions, weights),
-        };
-
-        // return unit matrix if two structures are already close enough
-        let rotation_matrix = if let Some(rot) = rot {
-            Matrix3f::from_row_slice(&rot)
-        } else {
-            Matrix3f::identity()
-        };
-
-        // return superimposition result
-        let sp = Superposition {
-            rmsd,
-            translation: trans.into(),
-            rotation_matrix,
-        };
+        let sp = self.algorithm.superimpose(reference, self.positions, weights);
 
         Ok(sp)
     }
@@ -180,6 +290,17 @@ pub struct Superpose<'a> {
 
     /// Select algo
     pub algorithm: SuperpositionAlgo,
+
+    /// Allow an improper rotation (rotation-reflection) if it gives a lower
+    /// RMSD, which is useful for comparing enantiomers.
+    pub allow_reflection: bool,
+
+    /// Also solve for an isotropic scale factor of the candidate structure
+    /// (similarity superposition), which removes the size difference.
+    pub allow_scaling: bool,
+
+    /// Indices of candidate and reference points used for fitting
+    selection: Option<(&'a [usize], &'a [usize])>,
 }
 
 impl<'a> Superpose<'a> {
@@ -188,6 +309,9 @@ impl<'a> Superpose<'a> {
         Self {
             positions,
             algorithm: SuperpositionAlgo::default(),
+            allow_reflection: false,
+            allow_scaling: false,
+            selection: None,
         }
     }
 
@@ -196,10 +320,13 @@ impl<'a> Superpose<'a> {
     /// Parameters
     /// ----------
     /// * reference: reference coordinates
-    /// * weights  : weight of each point
+    /// * weights  : weight of each point, or of each selected point if
+    ///   `with_selection` is used
     pub fn rmsd(&self, reference: &[[f64; 3]], weights: Option<&[f64]>) -> Result<f64> {
+        let (positions, reference) = self.fitted_points(reference)?;
+
         // sanity check
-        let npts = self.positions.len();
+        let npts = positions.len();
         if reference.len() != npts {
             bail!("points size mismatch!");
         }
@@ -212,9 +339,9 @@ impl<'a> Superpose<'a> {
         for i in 0..npts {
             // take the weight if any, or set it to 1.0
             let wi = weights.map_or_else(|| 1.0, |w| w[i]);
-            let dx = wi * (self.positions[i][0] - reference[i][0]);
-            let dy = wi * (self.positions[i][1] - reference[i][1]);
-            let dz = wi * (self.positions[i][2] - reference[i][2]);
+            let dx = wi * (positions[i][0] - reference[i][0]);
+            let dy = wi * (positions[i][1] - reference[i][1]);
+            let dz = wi * (positions[i][2] - reference[i][2]);
 
             ws += dx.powi(2) + dy.powi(2) + dz.powi(2);
         }
@@ -223,33 +350,64 @@ impl<'a> Superpose<'a> {
         Ok(ws)
     }
 
-    /// Superpose candidate structure onto reference structure which will be held fixed
-    /// Return superposition struct
+    /// Calculate the minimal Root-mean-square deviation of self with the
+    /// reference coordinates after optimal superposition. This is much faster
+    /// than `onto` as the rotation matrix is not constructed, unless
+    /// `allow_scaling` is set.
     ///
     /// Parameters
     /// ----------
     /// * reference: reference coordinates
     /// * weights  : weight of each point
-    pub fn onto(&mut self, reference: &[[f64; 3]], weights: Option<&[f64]>) -> Superposition {
-        // calculate the RMSD & rotational matrix
-        let (rmsd, trans, rot) = match self.algorithm {
-            SuperpositionAlgo::QCP => self::qcprot::calc_rmsd_rotational_matrix(&reference, &self.positions, weights),
-            SuperpositionAlgo::Quaternion => self::quaternion::calc_rmsd_rotational_matrix(&reference, &self.positions, weights),
-        };
+    pub fn min_rmsd(&self, reference: &[[f64; 3]], weights: Option<&[f64]>) -> Result<f64> {
+        let (candidate, selected) = self.fitted_points(reference)?;
 
-        // return unit matrix if two structures are already close enough
-        let rotation_matrix = if let Some(rot) = rot {
-            Matrix3f::from_row_slice(&rot)
-        } else {
-            Matrix3f::identity()
-        };
+        // sanity check
+        let npts = candidate.len();
+        if selected.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if weights.is_some_and(|w| w.len() != npts) {
+            bail!("weights size mismatch!");
+        }
 
-        // return superimposition result
-        Superposition {
-            rmsd,
-            translation: trans.into(),
-            rotation_matrix,
+        if self.allow_scaling {
+            // the optimal scale factor depends on the rotation matrix
+            return Ok(self.clone().onto(reference, weights).rmsd);
+        }
+        let rmsd = self::qcprot::calc_rmsd(&selected, &candidate, weights);
+        if !self.allow_reflection {
+            return Ok(rmsd);
         }
+        let mut mirrored = candidate.to_vec();
+        mirrored.mirror_invert();
+        Ok(rmsd.min(self::qcprot::calc_rmsd(&selected, &mirrored, weights)))
+    }
+
+    /// Superpose candidate structure onto reference structure which will be held fixed
+    /// Return superposition struct, whose rigid-body transformation is
+    /// available from `Superposition::transform`.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point, or of each selected point if
+    ///   `with_selection` is used
+    ///
+    /// # Panics
+    ///
+    /// * panics if selected indices are invalid.
+    pub fn onto(&mut self, reference: &[[f64; 3]], weights: Option<&[f64]>) -> Superposition {
+        let (candidate, reference) = self.fitted_points(reference).unwrap_or_else(|e| panic!("{}", e));
+        let algorithm = self.algorithm;
+        superimpose_with(
+            &candidate,
+            &reference,
+            weights,
+            self.allow_reflection,
+            self.allow_scaling,
+            |c| algorithm.superimpose(&reference, c, weights),
+        )
     }
 }
 // 62a7ce8f ends here
@@ -304,3 +462,117 @@ fn test_alignment_hcn() {
     approx::assert_relative_eq!(sp.rotation_matrix, r, epsilon = 1e-4);
 }
 // test:2 ends here
+
+// [[file:../gchemol-geometry.note::9c4b27d1][9c4b27d1]]
+#[test]
+fn test_alignment_kabsch() {
+    use vecfx::*;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+
+    let sp_qcp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    let mut spr = Superpose::new(&candidate);
+    spr.algorithm = SuperpositionAlgo::Kabsch;
+    let sp = spr.onto(&reference, Some(&weights));
+    approx::assert_relative_eq!(sp.rmsd, sp_qcp.rmsd, epsilon = 1e-6);
+    approx::assert_relative_eq!(sp.rotation_matrix, sp_qcp.rotation_matrix, epsilon = 1e-4);
+    approx::assert_relative_eq!(sp.translation, sp_qcp.translation, epsilon = 1e-4);
+}
+// 9c4b27d1 ends here
+
+// [[file:../gchemol-geometry.note::0b6d3e58][0b6d3e58]]
+#[test]
+fn test_alignment_min_rmsd() {
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+
+    let sp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    let rmsd = Superpose::new(&candidate).min_rmsd(&reference, Some(&weights)).unwrap();
+    approx::assert_relative_eq!(rmsd, sp.rmsd, epsilon = 1e-8);
+
+    let sp = Superpose::new(&candidate).onto(&reference, None);
+    let rmsd = Superpose::new(&candidate).min_rmsd(&reference, None).unwrap();
+    approx::assert_relative_eq!(rmsd, sp.rmsd, epsilon = 1e-8);
+
+    assert!(Superpose::new(&candidate).min_rmsd(&reference[1..], None).is_err());
+
+    // the same options as onto
+    let mut enantiomer = candidate.clone();
+    enantiomer.point_invert();
+    for (allow_reflection, allow_scaling) in [(true, false), (false, true), (true, true)] {
+        let mut sup = Superpose::new(&enantiomer);
+        sup.allow_reflection = allow_reflection;
+        sup.allow_scaling = allow_scaling;
+        let rmsd = sup.min_rmsd(&reference, Some(&weights)).unwrap();
+        approx::assert_relative_eq!(rmsd, sup.onto(&reference, Some(&weights)).rmsd, epsilon = 1e-8);
+    }
+}
+// 0b6d3e58 ends here
+
+// [[file:../gchemol-geometry.note::5d0e7a92][5d0e7a92]]
+#[test]
+fn test_alignment_reflection() {
+    use vecfx::*;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp_proper = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    assert!(!sp_proper.reflection);
+
+    // the enantiomer of the candidate
+    let mut enantiomer = candidate.clone();
+    enantiomer.point_invert();
+    let mut spr = Superpose::new(&enantiomer);
+    let sp = spr.onto(&reference, Some(&weights));
+    assert!(!sp.reflection);
+    assert!(sp.rmsd > sp_proper.rmsd);
+
+    spr.allow_reflection = true;
+    let sp = spr.onto(&reference, Some(&weights));
+    assert!(sp.reflection);
+    approx::assert_relative_eq!(sp.rmsd, sp_proper.rmsd, epsilon = 1e-6);
+    approx::assert_relative_eq!(sp.rotation_matrix.determinant(), -1.0, epsilon = 1e-6);
+
+    // the returned transformation reproduces the rmsd
+    let superimposed = sp.apply(&enantiomer);
+    let wsum: f64 = weights.iter().sum();
+    let msd: f64 = (0..reference.len())
+        .map(|i| weights[i] * superimposed[i].vecdist_squared(&reference[i]))
+        .sum();
+    approx::assert_relative_eq!((msd / wsum).sqrt(), sp.rmsd, epsilon = 1e-6);
+}
+// 5d0e7a92 ends here
+
+// [[file:../gchemol-geometry.note::a2f4c816][a2f4c816]]
+#[test]
+fn test_alignment_apply_rotation() {
+    use vecfx::*;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+
+    let rotated = sp.apply_rotation(&candidate);
+    let superimposed = sp.apply(&candidate);
+    for (p, q) in sp.apply_translation(&rotated).iter().zip(&superimposed) {
+        approx::assert_relative_eq!(Vector3f::from(*p), Vector3f::from(*q), epsilon = 1e-8);
+    }
+    // rotation keeps the distance to the origin
+    for (p, q) in rotated.iter().zip(&candidate) {
+        approx::assert_relative_eq!(Vector3f::from(*p).norm(), Vector3f::from(*q).norm(), epsilon = 1e-8);
+    }
+}
+// a2f4c816 ends here
+
+// [[file:../gchemol-geometry.note::6e1c0b95][6e1c0b95]]
+#[test]
+fn test_alignment_apply_infinite() {
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+
+    // infinite coordinates pass through apply, but not try_apply
+    let mut bad = candidate.clone();
+    bad[0] = [f64::INFINITY, 0.0, 0.0];
+    let superimposed = sp.apply(&bad);
+    assert!(superimposed[0].iter().any(|x| x.is_infinite()));
+    assert!(superimposed[1].iter().all(|x| x.is_finite()));
+    assert_eq!(sp.try_apply(&bad).unwrap_err(), SuperposeError::NonFinite);
+}
+// 6e1c0b95 ends here
@@ -0,0 +1,187 @@
+// [[file:../../gchemol-geometry.note::f3b05d8e][f3b05d8e]]
+use super::*;
+// f3b05d8e ends here
+
+// [[file:../../gchemol-geometry.note::2ac7e4b9][2ac7e4b9]]
+/// Return the superposition applying `rotation` then `translation`, with
+/// RMSD evaluated directly.
+fn constrained_superposition(
+    rotation: Matrix3f,
+    translation: Vector3f,
+    candidate: &[Point3],
+    reference: &[Point3],
+    weights: &[f64],
+) -> Superposition {
+    let mut sp = Superposition {
+        rmsd: 0.0,
+        translation,
+        rotation_matrix: rotation,
+        reflection: false,
+        scale: 1.0,
+    };
+    let wsum: f64 = weights.iter().sum();
+    let msd: f64 = sp
+        .apply(candidate)
+        .iter()
+        .zip(reference)
+        .zip(weights)
+        .map(|((p, r), w)| w * p.vecdist_squared(r))
+        .sum::<f64>()
+        / wsum;
+    sp.rmsd = msd.sqrt();
+    sp
+}
+
+impl<'a> Superpose<'a> {
+    /// Return the points for fitting and the weights, with sizes checked.
+    fn constrained_inputs(&self, reference: &[Point3], weights: Option<&[f64]>) -> Result<(Vec<Point3>, Vec<Point3>, Vec<f64>)> {
+        let (candidate, reference) = self.fitted_points(reference)?;
+        let npts = candidate.len();
+        if reference.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if npts == 0 {
+            bail!("no point for superposition!");
+        }
+        let weights = match weights {
+            Some(w) if w.len() != npts => bail!("weights size mismatch!"),
+            Some(w) => w.to_vec(),
+            None => vec![1.0; npts],
+        };
+        Ok((candidate.into_owned(), reference.into_owned(), weights))
+    }
+
+    /// Superpose candidate structure onto reference structure by translation
+    /// only.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point
+    pub fn onto_translation_only(&mut self, reference: &[Point3], weights: Option<&[f64]>) -> Result<Superposition> {
+        let (candidate, reference, weights) = self.constrained_inputs(reference, weights)?;
+        let com_can = Vector3f::from(crate::base::weighted_center_of_geometry(&candidate, &weights));
+        let com_ref = Vector3f::from(crate::base::weighted_center_of_geometry(&reference, &weights));
+
+        let sp = constrained_superposition(Matrix3f::identity(), com_ref - com_can, &candidate, &reference, &weights);
+        Ok(sp)
+    }
+
+    /// Superpose candidate structure onto reference structure by rotation
+    /// about a fixed `axis` direction, such as the surface normal, and
+    /// translation.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point
+    /// * axis     : the direction of rotation axis, not necessarily normalized
+    pub fn onto_about_axis(&mut self, reference: &[Point3], weights: Option<&[f64]>, axis: Point3) -> Result<Superposition> {
+        let (candidate, reference, weights) = self.constrained_inputs(reference, weights)?;
+        let axis = Vector3f::from(axis);
+        if axis.norm() < 1e-8 {
+            bail!("invalid rotation axis: {:?}", axis);
+        }
+        let u = axis.normalize();
+        let com_can = Vector3f::from(crate::base::weighted_center_of_geometry(&candidate, &weights));
+        let com_ref = Vector3f::from(crate::base::weighted_center_of_geometry(&reference, &weights));
+
+        // maximize sum w_i (R x_i) . y_i = A cos(θ) + B sin(θ) + C
+        let mut a = 0.0;
+        let mut b = 0.0;
+        for i in 0..candidate.len() {
+            let x = Vector3f::from(candidate[i]) - com_can;
+            let y = Vector3f::from(reference[i]) - com_ref;
+            a += weights[i] * (x.dot(&y) - u.dot(&x) * u.dot(&y));
+            b += weights[i] * u.cross(&x).dot(&y);
+        }
+        let angle = b.atan2(a);
+        let rotation = RigidTransform::from_axis_angle(u.into(), angle).rotation;
+
+        let sp = constrained_superposition(rotation, com_ref - rotation * com_can, &candidate, &reference, &weights);
+        Ok(sp)
+    }
+
+    /// Superpose candidate structure onto reference structure by rotation
+    /// about a fixed `pivot` point, without translation.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point
+    /// * pivot    : the point held fixed in rotation
+    pub fn onto_about_pivot(&mut self, reference: &[Point3], weights: Option<&[f64]>, pivot: Point3) -> Result<Superposition> {
+        let (candidate, reference, weights) = self.constrained_inputs(reference, weights)?;
+        let pivot = Vector3f::from(pivot);
+
+        // weighted covariance matrix between coordinates relative to the pivot
+        let mut mat_h = Matrix3f::zeros();
+        for i in 0..candidate.len() {
+            let x = Vector3f::from(candidate[i]) - pivot;
+            let y = Vector3f::from(reference[i]) - pivot;
+            mat_h += weights[i] * x * y.transpose();
+        }
+        let rotation = kabsch::optimal_rotation(&mat_h);
+
+        let sp = constrained_superposition(rotation, pivot - rotation * pivot, &candidate, &reference, &weights);
+        Ok(sp)
+    }
+}
+// 2ac7e4b9 ends here
+
+// [[file:../../gchemol-geometry.note::71d8c3f0][71d8c3f0]]
+#[test]
+fn test_superpose_constrained() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp_free = Superpose::new(&candidate).onto(&reference, Some(&weights));
+
+    // translation only
+    let shifted: Vec<Point3> = reference.iter().map(|p| [p[0] + 1.0, p[1] - 2.0, p[2] + 3.0]).collect();
+    let sp = Superpose::new(&shifted).onto_translation_only(&reference, None).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-8);
+    assert_relative_eq!(sp.rotation_matrix, Matrix3f::identity());
+    let sp = Superpose::new(&candidate).onto_translation_only(&reference, Some(&weights)).unwrap();
+    assert!(sp.rmsd >= sp_free.rmsd);
+
+    // rotation about an axis with translation
+    let t = RigidTransform::from_translation([1.0, -2.0, 3.0]) * RigidTransform::from_axis_angle([0.0, 0.0, 1.0], 2.5);
+    let moved = t.apply(&reference);
+    let sp = Superpose::new(&moved).onto_about_axis(&reference, None, [0.0, 0.0, 2.0]).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-8);
+    let (axis, angle) = sp.transform().unwrap().to_axis_angle().unwrap();
+    assert_relative_eq!(Vector3f::from(axis), Vector3f::new(0.0, 0.0, -1.0), epsilon = 1e-8);
+    assert_relative_eq!(angle, 2.5, epsilon = 1e-8);
+    let sp = Superpose::new(&candidate).onto_about_axis(&reference, Some(&weights), [0.3, 1.0, 0.0]).unwrap();
+    assert!(sp.rmsd >= sp_free.rmsd);
+    assert!(Superpose::new(&candidate).onto_about_axis(&reference, None, [0.0; 3]).is_err());
+
+    // rotation about a pivot without translation
+    let pivot = [0.5, -1.0, 2.0];
+    let t = RigidTransform::from_translation(pivot)
+        * RigidTransform::from_axis_angle([0.6, 0.2, -1.0], 1.8)
+        * RigidTransform::from_translation([-0.5, 1.0, -2.0]);
+    let moved = t.apply(&reference);
+    let sp = Superpose::new(&moved).onto_about_pivot(&reference, None, pivot).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-8);
+    assert_relative_eq!(Vector3f::from(sp.apply(&[pivot])[0]), Vector3f::from(pivot), epsilon = 1e-12);
+    let sp = Superpose::new(&candidate).onto_about_pivot(&reference, Some(&weights), pivot).unwrap();
+    assert!(sp.rmsd >= sp_free.rmsd);
+
+    // fit on selected points, with weights of selected points
+    let mut distorted = moved.clone();
+    distorted[6][0] += 2.0;
+    let selection = [0, 1, 2, 3, 4, 5];
+    let sp = Superpose::new(&distorted)
+        .with_selection(&selection, &selection)
+        .onto_about_pivot(&reference, Some(&[1.0; 6]), pivot)
+        .unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-8);
+    let sp = Superpose::new(&shifted)
+        .with_selection(&selection, &selection)
+        .onto_translation_only(&reference, Some(&[1.0; 6]))
+        .unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-8);
+}
+// 71d8c3f0 ends here
@@ -0,0 +1,126 @@
+// [[file:../../gchemol-geometry.note::7e5c0a13][7e5c0a13]]
+use super::*;
+// 7e5c0a13 ends here
+
+// [[file:../../gchemol-geometry.note::c9a04d2f][c9a04d2f]]
+/// The result of superposing multiple structures at once.
+#[derive(Clone, Debug)]
+pub struct EnsembleSuperposition {
+    /// The superposition of each structure onto the mean structure
+    pub superpositions: Vec<Superposition>,
+
+    /// The converged mean structure
+    pub mean_structure: Vec<Point3>,
+
+    /// The root-mean-square fluctuation of each point around the mean structure
+    pub rmsf: Vec<f64>,
+}
+
+/// Superpose multiple structures at once (generalized Procrustes analysis).
+/// All structures are iteratively superposed onto their evolving mean
+/// structure until the mean structure converges, which avoids the bias from
+/// the choice of a fixed reference. An error is returned if any structure
+/// cannot be superposed, such as for degenerate geometry.
+///
+/// Parameters
+/// ----------
+/// * conformers: the structures to be superposed, all of the same size
+/// * weights  : weight of each point
+pub fn superpose_ensemble(conformers: &[Vec<Point3>], weights: Option<&[f64]>) -> Result<EnsembleSuperposition> {
+    if conformers.is_empty() {
+        bail!("no structure for superposition!");
+    }
+    let npts = conformers[0].len();
+    if conformers.iter().any(|c| c.len() != npts) {
+        bail!("points size mismatch!");
+    }
+    if weights.is_some_and(|w| w.len() != npts) {
+        bail!("weights size mismatch!");
+    }
+
+    let nconf = conformers.len() as f64;
+    let mut mean_structure = conformers[0].clone();
+    let mut icycle = 0;
+    let (superpositions, superimposed) = loop {
+        let superpositions = conformers
+            .iter()
+            .map(|conf| Superpose::new(conf).try_onto(&mean_structure, weights))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let superimposed: Vec<_> = conformers.iter().zip(&superpositions).map(|(c, sp)| sp.apply(c)).collect();
+
+        // update the mean structure
+        let mut new_mean = vec![[0.0; 3]; npts];
+        for conf in &superimposed {
+            for (m, p) in new_mean.iter_mut().zip(conf) {
+                for v in 0..3 {
+                    m[v] += p[v] / nconf;
+                }
+            }
+        }
+        let change = Superpose::new(&new_mean).rmsd(&mean_structure, None)?;
+        mean_structure = new_mean;
+
+        icycle += 1;
+        if change < 1e-8 {
+            break (superpositions, superimposed);
+        }
+        if icycle >= 100 {
+            warn!("mean structure does not converge: change = {}", change);
+            break (superpositions, superimposed);
+        }
+    };
+
+    let rmsf = (0..npts)
+        .map(|i| {
+            let msf: f64 = superimposed.iter().map(|c| c[i].vecdist_squared(&mean_structure[i])).sum();
+            (msf / nconf).sqrt()
+        })
+        .collect();
+
+    Ok(EnsembleSuperposition {
+        superpositions,
+        mean_structure,
+        rmsf,
+    })
+}
+// c9a04d2f ends here
+
+// [[file:../../gchemol-geometry.note::52f1e8b6][52f1e8b6]]
+#[test]
+fn test_superpose_ensemble() {
+    use vecfx::approx::assert_relative_eq;
+    use vecfx::nalgebra::Rotation3;
+
+    let (reference, _, _) = super::qcprot::prepare_test_data();
+    let conformers: Vec<Vec<Point3>> = [(0.3, -1.2, 2.1, 0.2), (1.0, 0.5, -0.4, -0.2), (-0.7, 2.2, 0.9, 0.0)]
+        .iter()
+        .map(|&(a, b, c, dx)| {
+            let rot = Rotation3::from_euler_angles(a, b, c);
+            let mut conf: Vec<Point3> = reference.iter().map(|&p| (rot * Vector3f::from(p)).into()).collect();
+            // only the first point fluctuates
+            conf[0] = (rot * Vector3f::from([reference[0][0] + dx, reference[0][1], reference[0][2]])).into();
+            conf
+        })
+        .collect();
+
+    let ensemble = superpose_ensemble(&conformers, None).unwrap();
+    assert_eq!(ensemble.superpositions.len(), 3);
+    assert!(ensemble.rmsf[0] > 0.1);
+    for i in 1..7 {
+        assert!(ensemble.rmsf[i] < ensemble.rmsf[0]);
+    }
+
+    // the mean structure is close to the reference
+    let sp = Superpose::new(&ensemble.mean_structure).onto(&reference, None);
+    assert!(sp.rmsd < 0.1);
+
+    let ensemble = superpose_ensemble(&conformers[..1], None).unwrap();
+    assert_relative_eq!(ensemble.rmsf.iter().sum::<f64>(), 0.0, epsilon = 1e-8);
+    assert!(superpose_ensemble(&[], None).is_err());
+
+    // degenerate structures are errors rather than panics
+    let coincident = vec![[1.0, 2.0, 3.0]; 7];
+    let e = superpose_ensemble(&[coincident.clone(), coincident], None).unwrap_err();
+    assert_eq!(e.downcast_ref::<SuperposeError>(), Some(&SuperposeError::DegenerateGeometry));
+}
+// 52f1e8b6 ends here
@@ -0,0 +1,169 @@
+// [[file:../../gchemol-geometry.note::7b3d9e15][7b3d9e15]]
+use super::*;
+// 7b3d9e15 ends here
+
+// [[file:../../gchemol-geometry.note::f06c2a8d][f06c2a8d]]
+/// The error type for fallible superposition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SuperposeError {
+    /// The number of points differs between candidate and reference.
+    SizeMismatch { candidate: usize, reference: usize },
+    /// The number of weights differs from the number of points.
+    WeightsSizeMismatch { points: usize, weights: usize },
+    /// Weights with negative values or a zero sum.
+    InvalidWeights,
+    /// Input or result containing NaN or infinite numbers.
+    NonFinite,
+    /// All points coincide, so the orientation is undefined.
+    DegenerateGeometry,
+    /// No point for superposition.
+    TooFewPoints { found: usize },
+    /// Selected indices with different lengths or out of range.
+    InvalidSelection,
+}
+
+impl std::fmt::Display for SuperposeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::SizeMismatch { candidate, reference } => {
+                write!(f, "points size mismatch: candidate {}, reference {}", candidate, reference)
+            }
+            Self::WeightsSizeMismatch { points, weights } => {
+                write!(f, "weights size mismatch: points {}, weights {}", points, weights)
+            }
+            Self::InvalidWeights => write!(f, "weights are negative or sum to zero"),
+            Self::NonFinite => write!(f, "found invalid float numbers"),
+            Self::DegenerateGeometry => write!(f, "degenerate geometry: all points coincide"),
+            Self::TooFewPoints { found } => write!(f, "too few points for superposition: {}", found),
+            Self::InvalidSelection => write!(f, "invalid selection of point indices"),
+        }
+    }
+}
+
+impl std::error::Error for SuperposeError {}
+
+/// Return true if all points coincide within a tiny tolerance.
+fn is_degenerate(points: &[Point3], weights: &[f64]) -> bool {
+    let center = crate::base::weighted_center_of_geometry(points, weights);
+    points.iter().all(|p| p.vecdist_squared(&center) < 1e-16)
+}
+
+/// Check the inputs for superposition, and return the weights to be used.
+pub(super) fn check_inputs(
+    candidate: &[Point3],
+    reference: &[Point3],
+    weights: Option<&[f64]>,
+) -> std::result::Result<Vec<f64>, SuperposeError> {
+    let npts = candidate.len();
+    if reference.len() != npts {
+        return Err(SuperposeError::SizeMismatch {
+            candidate: npts,
+            reference: reference.len(),
+        });
+    }
+    if npts == 0 {
+        return Err(SuperposeError::TooFewPoints { found: npts });
+    }
+    if candidate.as_flat().iter().chain(reference.as_flat()).any(|x| !x.is_finite()) {
+        return Err(SuperposeError::NonFinite);
+    }
+
+    let weights = match weights {
+        Some(w) if w.len() != npts => {
+            return Err(SuperposeError::WeightsSizeMismatch {
+                points: npts,
+                weights: w.len(),
+            })
+        }
+        Some(w) => w.to_vec(),
+        None => vec![1.0; npts],
+    };
+    if weights.iter().any(|x| !x.is_finite()) {
+        return Err(SuperposeError::NonFinite);
+    }
+    if weights.iter().any(|&x| x < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+        return Err(SuperposeError::InvalidWeights);
+    }
+
+    if is_degenerate(candidate, &weights) || is_degenerate(reference, &weights) {
+        return Err(SuperposeError::DegenerateGeometry);
+    }
+
+    Ok(weights)
+}
+
+impl Superposition {
+    /// Return true if all numbers in the superposition are finite.
+    fn is_finite(&self) -> bool {
+        self.rmsd.is_finite() && self.scale.is_finite() && self.translation.iter().chain(self.rotation_matrix.iter()).all(|x| x.is_finite())
+    }
+
+    /// Apply superposition to other structure `conf`. Return an error instead
+    /// of panicking if any invalid float number is found.
+    pub fn try_apply(&self, conf: &[Point3]) -> std::result::Result<Vec<Point3>, SuperposeError> {
+        if !self.is_finite() || conf.as_flat().iter().any(|x| !x.is_finite()) {
+            return Err(SuperposeError::NonFinite);
+        }
+
+        let superimposed = conf
+            .iter()
+            .map(|&p| (self.scale * (self.rotation_matrix * Vector3f::from(p)) + self.translation).into())
+            .collect();
+        Ok(superimposed)
+    }
+}
+
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure which will be
+    /// held fixed. Return an error instead of panicking on invalid inputs.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point
+    pub fn try_onto(
+        &mut self,
+        reference: &[Point3],
+        weights: Option<&[f64]>,
+    ) -> std::result::Result<Superposition, SuperposeError> {
+        let (candidate, selected) = self.fitted_points(reference)?;
+        let weights = check_inputs(&candidate, &selected, weights)?;
+        let sp = self.onto(reference, Some(&weights));
+        if !sp.is_finite() {
+            return Err(SuperposeError::NonFinite);
+        }
+
+        Ok(sp)
+    }
+}
+// f06c2a8d ends here
+
+// [[file:../../gchemol-geometry.note::4c8a1e62][4c8a1e62]]
+#[test]
+fn test_try_onto() {
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+
+    let sp = Superpose::new(&candidate).try_onto(&reference, Some(&weights)).unwrap();
+    let sp_expected = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    approx::assert_relative_eq!(sp.rmsd, sp_expected.rmsd, epsilon = 1e-8);
+    assert!(sp.try_apply(&candidate).is_ok());
+
+    let e = Superpose::new(&candidate).try_onto(&reference[1..], None).unwrap_err();
+    assert_eq!(e, SuperposeError::SizeMismatch { candidate: 7, reference: 6 });
+    let e = Superpose::new(&candidate).try_onto(&reference, Some(&weights[1..])).unwrap_err();
+    assert_eq!(e, SuperposeError::WeightsSizeMismatch { points: 7, weights: 6 });
+    let e = Superpose::new(&candidate).try_onto(&reference, Some(&[0.0; 7])).unwrap_err();
+    assert_eq!(e, SuperposeError::InvalidWeights);
+    let e = Superpose::new(&[]).try_onto(&[], None).unwrap_err();
+    assert_eq!(e, SuperposeError::TooFewPoints { found: 0 });
+
+    let mut bad = candidate.clone();
+    bad[0][1] = f64::NAN;
+    let e = Superpose::new(&bad).try_onto(&reference, None).unwrap_err();
+    assert_eq!(e, SuperposeError::NonFinite);
+    assert_eq!(sp.try_apply(&bad).unwrap_err(), SuperposeError::NonFinite);
+
+    let e = Superpose::new(&[[1.0; 3]; 7]).try_onto(&reference, None).unwrap_err();
+    assert_eq!(e, SuperposeError::DegenerateGeometry);
+}
+// 4c8a1e62 ends here
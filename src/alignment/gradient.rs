@@ -0,0 +1,131 @@
+// [[file:../../gchemol-geometry.note::5a8c2d71][5a8c2d71]]
+use super::*;
+// 5a8c2d71 ends here
+
+// [[file:../../gchemol-geometry.note::e4f0b39c][e4f0b39c]]
+impl Superposition {
+    /// Return the gradient of the RMSD with respect to each point of the
+    /// `candidate` structure. `self` must be the optimal superposition of
+    /// `candidate` onto `reference` with the same `weights`, so the rotation
+    /// and translation can be held fixed in the evaluation.
+    ///
+    /// The gradient is set to zero when the RMSD vanishes, where the RMSD is
+    /// not differentiable.
+    pub fn rmsd_gradient(&self, candidate: &[Point3], reference: &[Point3], weights: Option<&[f64]>) -> Vec<Point3> {
+        let npts = candidate.len();
+        assert_eq!(npts, reference.len(), "points size mismatch!");
+
+        let wsum = weights.map_or(npts as f64, |w| w.iter().sum());
+        let superimposed = self.apply(candidate);
+        let msd: f64 = (0..npts)
+            .map(|i| weights.map_or(1.0, |w| w[i]) * superimposed[i].vecdist_squared(&reference[i]))
+            .sum::<f64>()
+            / wsum;
+        let rmsd = msd.sqrt();
+        if rmsd < 1e-12 {
+            return vec![[0.0; 3]; npts];
+        }
+
+        // dRMSD/dx_i = w_i s R^T (s R x_i + t - y_i) / (W * RMSD)
+        let rot_t = self.rotation_matrix.transpose();
+        (0..npts)
+            .map(|i| {
+                let wi = weights.map_or(1.0, |w| w[i]);
+                let d = Vector3f::from(superimposed[i]) - Vector3f::from(reference[i]);
+                (rot_t * d * (self.scale * wi / (wsum * rmsd))).into()
+            })
+            .collect()
+    }
+}
+
+impl<'a> Superpose<'a> {
+    /// Return the minimal RMSD of candidate structure with the reference, and
+    /// its gradient with respect to each candidate point, evaluated at the
+    /// optimal superposition. If `with_selection` is used, the gradient is
+    /// zero for points not selected.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point, or of each selected point if
+    ///   `with_selection` is used
+    pub fn rmsd_with_gradient(&mut self, reference: &[Point3], weights: Option<&[f64]>) -> Result<(f64, Vec<Point3>)> {
+        let (candidate, selected) = self.fitted_points(reference)?;
+        let npts = candidate.len();
+        if selected.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if weights.is_some_and(|w| w.len() != npts) {
+            bail!("weights size mismatch!");
+        }
+
+        let sp = self.onto(reference, weights);
+        let gradient = sp.rmsd_gradient(&candidate, &selected, weights);
+        let Some((icandidate, _)) = self.selection else {
+            return Ok((sp.rmsd, gradient));
+        };
+
+        // scatter the gradient of selected points to all candidate points
+        let mut full = vec![[0.0; 3]; self.positions.len()];
+        for (&i, g) in icandidate.iter().zip(gradient) {
+            for v in 0..3 {
+                full[i][v] += g[v];
+            }
+        }
+        Ok((sp.rmsd, full))
+    }
+}
+// e4f0b39c ends here
+
+// [[file:../../gchemol-geometry.note::2b9f64e0][2b9f64e0]]
+#[test]
+fn test_rmsd_gradient() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = super::qcprot::prepare_test_data();
+    let (rmsd, gradient) = Superpose::new(&candidate)
+        .rmsd_with_gradient(&reference, Some(&weights))
+        .unwrap();
+    assert_relative_eq!(rmsd, 0.745016, epsilon = 1e-3);
+
+    // compare with finite differences
+    let h = 1e-5;
+    for i in 0..candidate.len() {
+        for v in 0..3 {
+            let mut displaced = candidate.clone();
+            displaced[i][v] += h;
+            let rp = Superpose::new(&displaced).min_rmsd(&reference, Some(&weights)).unwrap();
+            displaced[i][v] -= 2.0 * h;
+            let rm = Superpose::new(&displaced).min_rmsd(&reference, Some(&weights)).unwrap();
+            assert_relative_eq!(gradient[i][v], (rp - rm) / (2.0 * h), epsilon = 1e-6);
+        }
+    }
+
+    // fit on selected points
+    let selection = [0, 2, 3, 5, 6];
+    let ws = [1.0, 3.0, 4.0, 6.0, 7.0];
+    let (rmsd, gradient) = Superpose::new(&candidate)
+        .with_selection(&selection, &selection)
+        .rmsd_with_gradient(&reference, Some(&ws))
+        .unwrap();
+    assert_eq!(gradient.len(), candidate.len());
+    let min_rmsd = |positions: &[Point3]| {
+        Superpose::new(positions)
+            .with_selection(&selection, &selection)
+            .min_rmsd(&reference, Some(&ws))
+            .unwrap()
+    };
+    assert_relative_eq!(rmsd, min_rmsd(&candidate), epsilon = 1e-8);
+    for i in 0..candidate.len() {
+        for v in 0..3 {
+            let mut displaced = candidate.clone();
+            displaced[i][v] += h;
+            let rp = min_rmsd(&displaced);
+            displaced[i][v] -= 2.0 * h;
+            let rm = min_rmsd(&displaced);
+            assert_relative_eq!(gradient[i][v], (rp - rm) / (2.0 * h), epsilon = 1e-6);
+        }
+    }
+    assert_eq!(gradient[1], [0.0; 3]);
+}
+// 2b9f64e0 ends here
@@ -0,0 +1,84 @@
+// [[file:../../gchemol-geometry.note::8e0b6d35][8e0b6d35]]
+/// Solve the linear assignment problem for a square cost matrix using the
+/// Hungarian algorithm (Kuhn-Munkres with potentials, O(n^3)).
+///
+/// Return the column assigned to each row, which minimizes the total cost.
+pub(crate) fn solve_assignment(costs: &[Vec<f64>]) -> Vec<usize> {
+    let n = costs.len();
+    debug_assert!(costs.iter().all(|row| row.len() == n), "cost matrix is not square");
+
+    // 1-based indexing following the classic formulation; p[j] is the row
+    // assigned to column j, 0 for a virtual row.
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut p = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let cur = costs[i0 - 1][j - 1] - u[i0] - v[j];
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+        // augmenting path
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=n {
+        assignment[p[j] - 1] = j - 1;
+    }
+    assignment
+}
+// 8e0b6d35 ends here
+
+// [[file:../../gchemol-geometry.note::c0b4f61d][c0b4f61d]]
+#[test]
+fn test_hungarian() {
+    let costs = vec![
+        vec![4.0, 1.0, 3.0],
+        vec![2.0, 0.0, 5.0],
+        vec![3.0, 2.0, 2.0],
+    ];
+    let assignment = solve_assignment(&costs);
+    assert_eq!(assignment, vec![1, 0, 2]);
+
+    assert!(solve_assignment(&[]).is_empty());
+}
+// c0b4f61d ends here
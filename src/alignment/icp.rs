@@ -0,0 +1,147 @@
+// [[file:../../gchemol-geometry.note::7a0e5c62][7a0e5c62]]
+use super::*;
+// 7a0e5c62 ends here
+
+// [[file:../../gchemol-geometry.note::e2c94d17][e2c94d17]]
+/// Return the nearest reference point of each candidate point, restricted to
+/// points with the same label if `labels` is given.
+fn match_nearest(candidate: &[Point3], reference: &[Point3], labels: Option<(&[usize], &[usize])>) -> Vec<Option<usize>> {
+    candidate
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            (0..reference.len())
+                .filter(|&j| labels.is_none_or(|(lc, lr)| lc[i] == lr[j]))
+                .min_by(|&a, &b| p.vecdist_squared(&reference[a]).total_cmp(&p.vecdist_squared(&reference[b])))
+        })
+        .collect()
+}
+
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure without any
+    /// point correspondence, using the iterative closest point (ICP) method.
+    /// Each candidate point is matched to its nearest reference point,
+    /// alternating with the superposition of matched points. The candidate
+    /// and the reference can have different numbers of points.
+    ///
+    /// The iterations start from several orientations obtained by matching
+    /// principal axes, and the best result is kept.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * labels: labels of candidate points, such as element numbers. If
+    ///   given, points are matched only to reference points with the same
+    ///   label.
+    /// * reference_labels: labels of reference points
+    ///
+    /// Return
+    /// ------
+    /// The superposition of matched points and the matching: `matching[i]`
+    /// is the reference point matched to candidate point `i`, or None if no
+    /// reference point has the same label. A selection set by
+    /// `with_selection` is not allowed.
+    pub fn onto_icp(
+        &mut self,
+        reference: &[Point3],
+        labels: Option<&[usize]>,
+        reference_labels: Option<&[usize]>,
+    ) -> Result<(Superposition, Vec<Option<usize>>)> {
+        self.reject_selection()?;
+        let labels = match (labels, reference_labels) {
+            (Some(lc), Some(lr)) => {
+                if lc.len() != self.positions.len() || lr.len() != reference.len() {
+                    bail!("labels size mismatch!");
+                }
+                Some((lc, lr))
+            }
+            (None, None) => None,
+            _ => bail!("labels are required for both candidate and reference!"),
+        };
+        if self.positions.is_empty() || reference.is_empty() {
+            bail!("no point for superposition!");
+        }
+
+        let center_can = Vector3f::from(self.positions.center_of_geometry());
+        let center_ref = Vector3f::from(reference.center_of_geometry());
+
+        let mut best: Option<(Superposition, Vec<Option<usize>>)> = None;
+        for rot in permutation::initial_rotations(self.positions, reference) {
+            let mut moved: Vec<Point3> = self
+                .positions
+                .iter()
+                .map(|&p| (rot * (Vector3f::from(p) - center_can) + center_ref).into())
+                .collect();
+
+            let mut matching = vec![];
+            for _ in 0..100 {
+                let new_matching = match_nearest(&moved, reference, labels);
+                if new_matching == matching {
+                    break;
+                }
+                matching = new_matching;
+
+                // fit the original candidate points onto their matches
+                let (matched_can, matched_ref): (Vec<_>, Vec<_>) = matching
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, m)| m.map(|j| (self.positions[i], reference[j])))
+                    .unzip();
+                if matched_can.is_empty() {
+                    bail!("no point matched by labels!");
+                }
+                let mut sp = Superpose::new(&matched_can);
+                sp.algorithm = self.algorithm;
+                sp.allow_reflection = self.allow_reflection;
+                let sp = sp.onto(&matched_ref, None);
+                moved = sp.apply(self.positions);
+                let better = match &best {
+                    Some((b, _)) => sp.rmsd < b.rmsd,
+                    None => true,
+                };
+                if better {
+                    best = Some((sp, matching.clone()));
+                }
+            }
+        }
+
+        Ok(best.expect("superposition"))
+    }
+}
+// e2c94d17 ends here
+
+// [[file:../../gchemol-geometry.note::3b86f0a4][3b86f0a4]]
+#[test]
+fn test_superpose_icp() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, _, _) = super::qcprot::prepare_test_data();
+    let t = RigidTransform::from_axis_angle([-0.8, 0.3, 0.6], 2.0) * RigidTransform::from_translation([-1.0, 3.5, 0.5]);
+    let transform = |i: usize| t.apply_point(reference[i]);
+
+    // the candidate: rotated and translated reference with points shuffled
+    let order = [4, 6, 1, 0, 3, 5, 2];
+    let shuffled: Vec<Point3> = order.iter().map(|&i| transform(i)).collect();
+    let (sp, matching) = Superpose::new(&shuffled).onto_icp(&reference, None, None).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+    for (i, m) in matching.iter().enumerate() {
+        assert_eq!(*m, Some(order[i]));
+    }
+
+    // a partial candidate with labels
+    let labels_ref = vec![6, 6, 6, 8, 8, 1, 1];
+    let partial: Vec<Point3> = order[..5].iter().map(|&i| transform(i)).collect();
+    let labels_can: Vec<_> = order[..5].iter().map(|&i| labels_ref[i]).collect();
+    let (sp, matching) = Superpose::new(&partial).onto_icp(&reference, Some(&labels_can), Some(&labels_ref)).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+    assert_eq!(matching[0], Some(4));
+
+    assert!(Superpose::new(&partial).onto_icp(&reference, Some(&labels_can), None).is_err());
+    let selection = [0, 1, 2];
+    let e = Superpose::new(&partial)
+        .with_selection(&selection, &selection)
+        .onto_icp(&reference, None, None)
+        .unwrap_err();
+    assert_eq!(e.downcast_ref::<SuperposeError>(), Some(&SuperposeError::InvalidSelection));
+}
+// 3b86f0a4 ends here
@@ -0,0 +1,112 @@
+// [[file:../../gchemol-geometry.note::3b8e51c4][3b8e51c4]]
+use super::*;
+
+use vecfx::nalgebra as na;
+// 3b8e51c4 ends here
+
+// [[file:../../gchemol-geometry.note::d1a96e07][d1a96e07]]
+/// Return the optimal rotation matrix for the (weighted) covariance matrix
+/// `mat_h` between centered candidate and reference coordinates, using the
+/// singular value decomposition of `mat_h`.
+///
+/// The handedness is corrected so that the result is always a proper rotation
+/// (det = +1), even for planar or linear point sets.
+pub(super) fn optimal_rotation(mat_h: &Matrix3f) -> Matrix3f {
+    let svd = mat_h.svd(true, true);
+    let u = svd.u.expect("svd u");
+    let v = svd.v_t.expect("svd v_t").transpose();
+
+    // correct improper rotation
+    let d = (v * u.transpose()).determinant();
+    let d = if d < 0.0 { -1.0 } else { 1.0 };
+    let mat_d = Matrix3f::from_diagonal(&na::Vector3::new(1.0, 1.0, d));
+
+    v * mat_d * u.transpose()
+}
+
+/// Calculate the RMSD & rotational matrix using the Kabsch algorithm.
+///
+/// Superposition positions_can onto positions_ref -- in other words,
+/// positions_can is rotated, positions_ref is held fixed
+pub(super) fn calc_rmsd_rotational_matrix(
+    positions_ref: &[[f64; 3]],
+    positions_can: &[[f64; 3]],
+    weights: Option<&[f64]>,
+) -> (f64, [f64; 3], Option<[f64; 9]>) {
+    info!("calculate using Kabsch algorithm ...");
+
+    let npts = positions_ref.len();
+    assert_eq!(npts, positions_can.len(), "invalid position array size");
+
+    // set up weights for atoms
+    let default_weights = vec![1.0; npts];
+    let weights = weights.unwrap_or(&default_weights);
+    let wsum = weights.sum();
+
+    let com_ref: Vector3f = crate::base::weighted_center_of_geometry(positions_ref, weights).into();
+    let com_can: Vector3f = crate::base::weighted_center_of_geometry(positions_can, weights).into();
+
+    // 1. weighted covariance matrix between centered coordinates
+    let mut mat_h = Matrix3f::zeros();
+    for i in 0..npts {
+        let vcan = Vector3f::from(positions_can[i]) - com_can;
+        let vref = Vector3f::from(positions_ref[i]) - com_ref;
+        mat_h += weights[i] * vcan * vref.transpose();
+    }
+
+    // 2. optimal rotation from SVD with handedness correction
+    let mat_r = optimal_rotation(&mat_h);
+
+    // 3. superposition rmsd, evaluated directly to avoid cancellation errors
+    let mut msd = 0.0;
+    for i in 0..npts {
+        let vcan = Vector3f::from(positions_can[i]) - com_can;
+        let vref = Vector3f::from(positions_ref[i]) - com_ref;
+        msd += weights[i] * (mat_r * vcan - vref).norm_squared();
+    }
+    let rmsd = (msd / wsum).sqrt();
+
+    // 4. translation
+    let trans = com_ref - mat_r * com_can;
+
+    let mut rot = [0.0; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            rot[3 * i + j] = mat_r[(i, j)];
+        }
+    }
+
+    (rmsd, trans.into(), Some(rot))
+}
+// d1a96e07 ends here
+
+// [[file:../../gchemol-geometry.note::6a0f3c2e][6a0f3c2e]]
+#[test]
+fn test_kabsch() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (frag_a, frag_b, weights) = super::qcprot::prepare_test_data();
+    let (rmsd, trans, rot) = calc_rmsd_rotational_matrix(&frag_a, &frag_b, Some(&weights));
+    let (rmsd_qcp, trans_qcp, rot_qcp) = super::qcprot::calc_rmsd_rotational_matrix(&frag_a, &frag_b, Some(&weights));
+    assert_relative_eq!(rmsd, rmsd_qcp, epsilon = 1e-6);
+    assert_relative_eq!(rmsd, 0.745016, epsilon = 1e-3);
+
+    let rot = rot.expect("rot matrix");
+    let rot_qcp = rot_qcp.expect("rot matrix");
+    for i in 0..9 {
+        assert_relative_eq!(rot[i], rot_qcp[i], epsilon = 1e-4);
+    }
+    for i in 0..3 {
+        assert_relative_eq!(trans[i], trans_qcp[i], epsilon = 1e-4);
+    }
+
+    // planar structure: the result must be a proper rotation
+    let positions_ref = [[0.83334699, 0.716865, 0.0], [-0.5486581, -0.35588, 0.0], [-0.2855828, 1.036928, 0.0]];
+    let positions_can = [[-0.634504, -0.199638, -0.0], [0.970676, 0.670662, 0.0], [-0.337065, 0.926883, 0.0]];
+    let (rmsd, _, rot) = calc_rmsd_rotational_matrix(&positions_ref, &positions_can, None);
+    let rot = Matrix3f::from_row_slice(&rot.unwrap());
+    assert_relative_eq!(rot.determinant(), 1.0, epsilon = 1e-8);
+    let (rmsd_qcp, _, _) = super::qcprot::calc_rmsd_rotational_matrix(&positions_ref, &positions_can, None);
+    assert_relative_eq!(rmsd, rmsd_qcp, epsilon = 1e-6);
+}
+// 6a0f3c2e ends here
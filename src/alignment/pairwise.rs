@@ -0,0 +1,100 @@
+// [[file:../../gchemol-geometry.note::1d7f3b90][1d7f3b90]]
+use super::*;
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+// 1d7f3b90 ends here
+
+// [[file:../../gchemol-geometry.note::f3c86a2e][f3c86a2e]]
+/// Return the index of the pair (i, j) with i < j in a condensed distance
+/// matrix of `n` items, in the same order as scipy's `pdist`.
+pub fn condensed_index(n: usize, i: usize, j: usize) -> usize {
+    debug_assert!(i < j && j < n, "invalid pair: ({}, {})", i, j);
+    n * i - i * (i + 1) / 2 + j - i - 1
+}
+
+/// Calculate minimal RMSDs between all pairs of `conformers` using the QCP
+/// kernel, and return them as a condensed matrix (the upper triangle in row
+/// major order). The rotation matrices are never constructed.
+///
+/// The calculation runs in parallel when the `rayon` feature is enabled.
+///
+/// Parameters
+/// ----------
+/// * conformers: the structures to be compared, all of the same size
+/// * weights  : weight of each point
+pub fn pairwise_rmsd_condensed(conformers: &[Vec<Point3>], weights: Option<&[f64]>) -> Result<Vec<f64>> {
+    let nconf = conformers.len();
+    let npts = conformers.first().map_or(0, |c| c.len());
+    if conformers.iter().any(|c| c.len() != npts) {
+        bail!("points size mismatch!");
+    }
+    if weights.is_some_and(|w| w.len() != npts) {
+        bail!("weights size mismatch!");
+    }
+
+    // prepare each structure once as the reference of its row
+    let calc_row = |i: usize| -> Vec<f64> {
+        let reference = qcprot::QcpReference::new(&conformers[i], weights);
+        (i + 1..nconf).map(|j| reference.calc_rmsd(&conformers[j])).collect()
+    };
+
+    #[cfg(feature = "rayon")]
+    let rows: Vec<_> = (0..nconf).into_par_iter().map(calc_row).collect();
+    #[cfg(not(feature = "rayon"))]
+    let rows: Vec<_> = (0..nconf).map(calc_row).collect();
+
+    Ok(rows.concat())
+}
+
+/// Calculate minimal RMSDs between all pairs of `conformers`, and return
+/// them as a full N x N symmetric matrix.
+///
+/// Parameters
+/// ----------
+/// * conformers: the structures to be compared, all of the same size
+/// * weights  : weight of each point
+pub fn pairwise_rmsd_matrix(conformers: &[Vec<Point3>], weights: Option<&[f64]>) -> Result<Vec<Vec<f64>>> {
+    let n = conformers.len();
+    let condensed = pairwise_rmsd_condensed(conformers, weights)?;
+
+    let mut matrix = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let rmsd = condensed[condensed_index(n, i, j)];
+            matrix[i][j] = rmsd;
+            matrix[j][i] = rmsd;
+        }
+    }
+
+    Ok(matrix)
+}
+// f3c86a2e ends here
+
+// [[file:../../gchemol-geometry.note::09a5d4c7][09a5d4c7]]
+#[test]
+fn test_pairwise_rmsd() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (frag_a, frag_b, weights) = qcprot::prepare_test_data();
+    let mut frag_c = frag_a.clone();
+    frag_c[0][0] += 0.5;
+    let conformers = vec![frag_a, frag_b, frag_c];
+
+    let matrix = pairwise_rmsd_matrix(&conformers, Some(&weights)).unwrap();
+    for i in 0..3 {
+        assert_eq!(matrix[i][i], 0.0);
+        for j in 0..3 {
+            let sp = Superpose::new(&conformers[j]).onto(&conformers[i], Some(&weights));
+            assert_relative_eq!(matrix[i][j], sp.rmsd, epsilon = 1e-6);
+        }
+    }
+    assert_relative_eq!(matrix[0][1], 0.745016, epsilon = 1e-3);
+
+    let condensed = pairwise_rmsd_condensed(&conformers, Some(&weights)).unwrap();
+    assert_eq!(condensed.len(), 3);
+    assert_eq!(condensed[condensed_index(3, 1, 2)], matrix[1][2]);
+
+    assert!(pairwise_rmsd_condensed(&[], None).unwrap().is_empty());
+}
+// 09a5d4c7 ends here
@@ -0,0 +1,158 @@
+// [[file:../../gchemol-geometry.note::4d7b2e90][4d7b2e90]]
+use super::*;
+// 4d7b2e90 ends here
+
+// [[file:../../gchemol-geometry.note::a93f6c15][a93f6c15]]
+/// The result of superposition in a periodic cell.
+#[derive(Clone, Debug)]
+pub struct PeriodicSuperposition {
+    /// The superposition of `unwrapped` candidate positions onto the reference
+    pub superposition: Superposition,
+
+    /// The candidate positions unwrapped to the minimum images relative to
+    /// the reference, after applying the lattice translation
+    pub unwrapped: Vec<Point3>,
+
+    /// The fractional lattice translation applied to the candidate
+    pub lattice_translation: Point3,
+}
+
+/// Return each candidate point moved to its periodic image closest to the
+/// corresponding reference point. `mat_l` has lattice vectors as columns.
+fn unwrap_min_image(candidate: &[Point3], reference: &[Point3], mat_l: &Matrix3f, mat_l_inv: &Matrix3f) -> Vec<Point3> {
+    candidate
+        .iter()
+        .zip(reference)
+        .map(|(&p, &r)| {
+            let r = Vector3f::from(r);
+            let d = Vector3f::from(p) - r;
+            let f = mat_l_inv * d;
+            let d = mat_l * f.map(|x| x - x.round());
+            // the rounded image may not be the nearest one for skewed cells
+            let mut best = d;
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    for k in -1..=1 {
+                        let image = d + mat_l * Vector3f::new(i as f64, j as f64, k as f64);
+                        if image.norm_squared() < best.norm_squared() {
+                            best = image;
+                        }
+                    }
+                }
+            }
+            (r + best).into()
+        })
+        .collect()
+}
+
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure in a periodic
+    /// cell. Candidate points are unwrapped to their minimum images relative
+    /// to the corresponding reference points before fitting, so the
+    /// candidate should be roughly oriented as the reference.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point
+    /// * cell     : the lattice vectors as rows
+    /// * lattice_translations: fractional translations of the candidate to
+    ///   be tried before unwrapping, such as [0.5, 0.0, 0.0] for a supercell
+    ///   doubled along a. The zero translation is always tried, and the one
+    ///   giving the lowest RMSD is kept.
+    ///
+    /// A selection set by `with_selection` is not allowed, as points are
+    /// unwrapped pairwise over the whole structures.
+    pub fn onto_periodic(
+        &mut self,
+        reference: &[Point3],
+        weights: Option<&[f64]>,
+        cell: [Point3; 3],
+        lattice_translations: &[Point3],
+    ) -> Result<PeriodicSuperposition> {
+        self.reject_selection()?;
+        let npts = self.positions.len();
+        if reference.len() != npts {
+            bail!("points size mismatch!");
+        }
+        let mat_l = Matrix3f::from_columns(&cell.map(Vector3f::from));
+        let Some(mat_l_inv) = mat_l.try_inverse() else {
+            bail!("invalid lattice vectors: {:?}", cell);
+        };
+
+        let fit = |t: Point3| -> Result<PeriodicSuperposition> {
+            let shift = mat_l * Vector3f::from(t);
+            let shifted: Vec<Point3> = self.positions.iter().map(|&p| (Vector3f::from(p) + shift).into()).collect();
+            let unwrapped = unwrap_min_image(&shifted, reference, &mat_l, &mat_l_inv);
+            let sp = Superpose {
+                positions: &unwrapped,
+                ..*self
+            }
+            .try_onto(reference, weights)?;
+            Ok(PeriodicSuperposition {
+                superposition: sp,
+                unwrapped,
+                lattice_translation: t,
+            })
+        };
+
+        let mut best = fit([0.0; 3])?;
+        for &t in lattice_translations {
+            let psp = fit(t)?;
+            if psp.superposition.rmsd < best.superposition.rmsd {
+                best = psp;
+            }
+        }
+
+        Ok(best)
+    }
+}
+// a93f6c15 ends here
+
+// [[file:../../gchemol-geometry.note::c0e81f4a][c0e81f4a]]
+#[test]
+fn test_superpose_periodic() {
+    use vecfx::approx::assert_relative_eq;
+
+    let reference = vec![[9.2, 1.0, 5.0], [0.4, 1.5, 5.2], [9.5, 9.6, 4.1], [1.1, 0.3, 6.0]];
+    let cell = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 12.0]];
+
+    // slightly rotated candidate with points wrapped into the cell
+    let t = RigidTransform::from_translation([0.9, 0.6, 0.3]) * RigidTransform::from_axis_angle([0.0, 0.0, 1.0], 0.05);
+    let wrapped: Vec<Point3> = t
+        .apply(&reference)
+        .iter()
+        .map(|p| [p[0].rem_euclid(10.0), p[1].rem_euclid(10.0), p[2].rem_euclid(12.0)])
+        .collect();
+    let sp = Superpose::new(&wrapped).onto(&reference, None);
+    assert!(sp.rmsd > 1.0);
+    let psp = Superpose::new(&wrapped).onto_periodic(&reference, None, cell, &[]).unwrap();
+    assert_relative_eq!(psp.superposition.rmsd, 0.0, epsilon = 1e-6);
+    assert_eq!(psp.lattice_translation, [0.0; 3]);
+    let superimposed = psp.superposition.apply(&psp.unwrapped);
+    assert_relative_eq!(Vector3f::from(superimposed[2]), Vector3f::from(reference[2]), epsilon = 1e-6);
+
+    // displaced by half of a doubled cell, where unwrapping alone splits points
+    let reference = vec![[9.2, 1.0, 5.0], [10.4, 1.5, 5.2], [9.5, -0.4, 4.1], [11.1, 0.3, 6.0]];
+    let cell = [[20.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 12.0]];
+    let t = RigidTransform::from_axis_angle([0.0, 0.0, 1.0], 0.05);
+    let shifted: Vec<Point3> = t
+        .apply(&reference)
+        .iter()
+        .map(|p| [(p[0] + 10.0).rem_euclid(20.0), p[1].rem_euclid(10.0), p[2]])
+        .collect();
+    let psp = Superpose::new(&shifted).onto_periodic(&reference, None, cell, &[]).unwrap();
+    assert!(psp.superposition.rmsd > 1.0);
+    let psp = Superpose::new(&shifted).onto_periodic(&reference, None, cell, &[[0.5, 0.0, 0.0]]).unwrap();
+    assert_relative_eq!(psp.superposition.rmsd, 0.0, epsilon = 1e-6);
+    assert_eq!(psp.lattice_translation, [0.5, 0.0, 0.0]);
+
+    assert!(Superpose::new(&shifted).onto_periodic(&reference, None, [[0.0; 3]; 3], &[]).is_err());
+    let selection = [0, 1, 2];
+    let e = Superpose::new(&shifted)
+        .with_selection(&selection, &selection)
+        .onto_periodic(&reference, None, cell, &[])
+        .unwrap_err();
+    assert_eq!(e.downcast_ref::<SuperposeError>(), Some(&SuperposeError::InvalidSelection));
+}
+// c0e81f4a ends here
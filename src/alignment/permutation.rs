@@ -0,0 +1,197 @@
+// [[file:../../gchemol-geometry.note::4f27a9c3][4f27a9c3]]
+use super::*;
+
+use std::collections::BTreeMap;
+// 4f27a9c3 ends here
+
+// [[file:../../gchemol-geometry.note::b6e1d0a8][b6e1d0a8]]
+/// Return initial rotations to bring the candidate roughly onto the
+/// reference: the identity and the proper principal axes matchings.
+pub(super) fn initial_rotations(candidate: &[Point3], reference: &[Point3]) -> Vec<Matrix3f> {
+    // principal axes as matrix columns
+    let principal_axes = |points: &[Point3]| {
+        let axes = points.principal_axes(&vec![1.0; points.len()]);
+        Matrix3f::from_columns(&axes.map(Vector3f::from))
+    };
+    let axes_can = principal_axes(candidate);
+    let axes_ref = principal_axes(reference);
+
+    let mut rotations = vec![Matrix3f::identity()];
+    for &(sx, sy) in &[(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)] {
+        let mut flip = Matrix3f::from_diagonal(&Vector3f::new(sx, sy, 1.0));
+        let r = axes_ref * flip * axes_can.transpose();
+        if r.determinant() < 0.0 {
+            flip[(2, 2)] = -1.0;
+        }
+        rotations.push(axes_ref * flip * axes_can.transpose());
+    }
+    rotations
+}
+
+/// Return the candidate point assigned to each reference point, by solving
+/// the linear assignment problem within each label class.
+fn assign_by_labels(candidate: &[Point3], reference: &[Point3], classes: &BTreeMap<usize, (Vec<usize>, Vec<usize>)>) -> Vec<usize> {
+    let mut perm = vec![0; reference.len()];
+    for (ican, iref) in classes.values() {
+        let costs: Vec<Vec<f64>> = iref
+            .iter()
+            .map(|&i| ican.iter().map(|&j| reference[i].vecdist_squared(&candidate[j])).collect())
+            .collect();
+        let assignment = super::hungarian::solve_assignment(&costs);
+        for (k, &i) in iref.iter().enumerate() {
+            perm[i] = ican[assignment[k]];
+        }
+    }
+    perm
+}
+
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure without
+    /// assuming that point i in the candidate corresponds to point i in the
+    /// reference. The optimal assignment of points is solved within each
+    /// class of the same label, alternating with the superposition.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * labels: labels of candidate points, such as element numbers
+    /// * reference_labels: labels of reference points
+    ///
+    /// Return
+    /// ------
+    /// The superposition and the permutation `perm` used: `candidate[perm[i]]`
+    /// is matched to `reference[i]`. An error is returned for fewer than
+    /// three points or coincident points, where the orientation is
+    /// undefined. A selection set by `with_selection` is not allowed.
+    pub fn onto_permuted(
+        &mut self,
+        reference: &[Point3],
+        labels: &[usize],
+        reference_labels: &[usize],
+    ) -> Result<(Superposition, Vec<usize>)> {
+        self.reject_selection()?;
+        let npts = self.positions.len();
+        if reference.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if labels.len() != npts || reference_labels.len() != npts {
+            bail!("labels size mismatch!");
+        }
+        if npts < 3 {
+            bail!("too few points for permuted superposition: {}", npts);
+        }
+        super::error::check_inputs(self.positions, reference, None)?;
+
+        // group point indices by labels
+        let mut classes: BTreeMap<usize, (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+        for (i, &l) in labels.iter().enumerate() {
+            classes.entry(l).or_default().0.push(i);
+        }
+        for (i, &l) in reference_labels.iter().enumerate() {
+            classes.entry(l).or_default().1.push(i);
+        }
+        if classes.values().any(|(ican, iref)| ican.len() != iref.len()) {
+            bail!("labels of candidate and reference are different!");
+        }
+
+        let center_can = Vector3f::from(self.positions.center_of_geometry());
+        let center_ref = Vector3f::from(reference.center_of_geometry());
+
+        let mut best: Option<(Superposition, Vec<usize>)> = None;
+        for rot in initial_rotations(self.positions, reference) {
+            let mut moved: Vec<Point3> = self
+                .positions
+                .iter()
+                .map(|&p| (rot * (Vector3f::from(p) - center_can) + center_ref).into())
+                .collect();
+
+            let mut perm: Vec<usize> = vec![];
+            for _ in 0..100 {
+                let new_perm = assign_by_labels(&moved, reference, &classes);
+                if new_perm == perm {
+                    break;
+                }
+                perm = new_perm;
+
+                // fit the original candidate points in assigned order
+                let permuted: Vec<_> = perm.iter().map(|&i| self.positions[i]).collect();
+                let mut sp = Superpose::new(&permuted);
+                sp.algorithm = self.algorithm;
+                sp.allow_reflection = self.allow_reflection;
+                let sp = match sp.try_onto(reference, None) {
+                    // QCP breaks down for collinear points, which Kabsch handles
+                    Err(SuperposeError::NonFinite) => {
+                        sp.algorithm = SuperpositionAlgo::Kabsch;
+                        sp.try_onto(reference, None)?
+                    }
+                    r => r?,
+                };
+                moved = sp.apply(self.positions);
+                let better = match &best {
+                    Some((b, _)) => sp.rmsd < b.rmsd,
+                    None => true,
+                };
+                if better {
+                    best = Some((sp, perm.clone()));
+                }
+            }
+        }
+
+        match best {
+            Some(best) => Ok(best),
+            None => bail!("permuted superposition failed!"),
+        }
+    }
+}
+// b6e1d0a8 ends here
+
+// [[file:../../gchemol-geometry.note::e83a5f17][e83a5f17]]
+#[test]
+fn test_superpose_permuted() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, _, _) = super::qcprot::prepare_test_data();
+    // the candidate: rotated and translated reference with points shuffled
+    let t = RigidTransform::from_axis_angle([0.4, 1.0, -0.6], -1.7) * RigidTransform::from_translation([-2.5, 0.8, 1.2]);
+    let order = [5, 2, 0, 6, 3, 1, 4];
+    let shuffled: Vec<Point3> = order.iter().map(|&i| t.apply_point(reference[i])).collect();
+
+    let labels = vec![1; 7];
+    let (sp, perm) = Superpose::new(&shuffled).onto_permuted(&reference, &labels, &labels).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+    for i in 0..7 {
+        assert_eq!(order[perm[i]], i);
+    }
+
+    // assignment restricted by labels
+    let labels_ref = vec![6, 6, 6, 8, 8, 1, 1];
+    let labels_can: Vec<_> = order.iter().map(|&i| labels_ref[i]).collect();
+    let (_, perm) = Superpose::new(&shuffled).onto_permuted(&reference, &labels_can, &labels_ref).unwrap();
+    for i in 0..7 {
+        assert_eq!(labels_can[perm[i]], labels_ref[i]);
+    }
+
+    let labels_bad = vec![6, 6, 6, 6, 8, 1, 1];
+    assert!(Superpose::new(&shuffled).onto_permuted(&reference, &labels_bad, &labels_ref).is_err());
+
+    // too few or coincident points
+    assert!(Superpose::new(&[]).onto_permuted(&[], &[], &[]).is_err());
+    assert!(Superpose::new(&shuffled[..1]).onto_permuted(&reference[..1], &[1], &[1]).is_err());
+    let coincident = vec![[1.0, 2.0, 3.0]; 7];
+    assert!(Superpose::new(&coincident).onto_permuted(&reference, &labels, &labels).is_err());
+
+    // linear molecules, such as CO2 with swapped oxygens
+    let co2 = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.16], [0.0, 0.0, -1.16]];
+    let rotated: Vec<Point3> = [0, 2, 1].iter().map(|&i| t.apply_point(co2[i])).collect();
+    let (sp, perm) = Superpose::new(&rotated).onto_permuted(&co2, &[6, 8, 8], &[6, 8, 8]).unwrap();
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+    assert_eq!(perm[0], 0);
+
+    let selection = [0, 1, 2];
+    let e = Superpose::new(&shuffled)
+        .with_selection(&selection, &selection)
+        .onto_permuted(&reference, &labels, &labels)
+        .unwrap_err();
+    assert_eq!(e.downcast_ref::<SuperposeError>(), Some(&SuperposeError::InvalidSelection));
+}
+// e83a5f17 ends here
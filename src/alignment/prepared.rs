@@ -0,0 +1,138 @@
+// [[file:../../gchemol-geometry.note::c17e5b3a][c17e5b3a]]
+use super::*;
+// c17e5b3a ends here
+
+// [[file:../../gchemol-geometry.note::80d2a6f9][80d2a6f9]]
+/// A reference structure prepared for superposing many structures onto it,
+/// such as all frames of a long trajectory. The weighted center, the weights
+/// and the self inner product of the reference are computed only once.
+///
+/// # Example
+///
+/// ```ignore
+/// let prepared = PreparedReference::new(&reference, None)?;
+/// let rmsds: Vec<_> = frames.iter().map(|f| prepared.fit(f).map(|sp| sp.rmsd)).collect::<Result<_>>()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct PreparedReference {
+    inner: qcprot::QcpReference,
+    reference: Vec<Point3>,
+    weights: Option<Vec<f64>>,
+
+    /// Allow an improper rotation (rotation-reflection) if it gives a lower
+    /// RMSD, as in `Superpose`.
+    pub allow_reflection: bool,
+
+    /// Also solve for an isotropic scale factor of each frame, as in
+    /// `Superpose`.
+    pub allow_scaling: bool,
+}
+
+impl PreparedReference {
+    /// Prepare `reference` structure with weight of each point.
+    pub fn new(reference: &[Point3], weights: Option<&[f64]>) -> Result<Self> {
+        if weights.is_some_and(|w| w.len() != reference.len()) {
+            bail!("weights size mismatch!");
+        }
+
+        let inner = qcprot::QcpReference::new(reference, weights);
+        Ok(Self {
+            inner,
+            reference: reference.to_vec(),
+            weights: weights.map(|w| w.to_vec()),
+            allow_reflection: false,
+            allow_scaling: false,
+        })
+    }
+
+    /// Superpose `frame` onto the reference structure using the QCP algorithm.
+    pub fn fit(&self, frame: &[Point3]) -> Result<Superposition> {
+        if frame.len() != self.inner.len() {
+            bail!("points size mismatch!");
+        }
+
+        let sp = superimpose_with(
+            frame,
+            &self.reference,
+            self.weights.as_deref(),
+            self.allow_reflection,
+            self.allow_scaling,
+            |c| self.fit_proper(c),
+        );
+        Ok(sp)
+    }
+
+    /// Superpose `frame` onto the reference structure by a proper rotation.
+    fn fit_proper(&self, frame: &[Point3]) -> Superposition {
+        let (rmsd, trans, rot) = self.inner.calc_rmsd_rotational_matrix(frame);
+        let rotation_matrix = rot.map_or_else(Matrix3f::identity, |rot| Matrix3f::from_row_slice(&rot));
+        Superposition {
+            rmsd,
+            translation: trans.into(),
+            rotation_matrix,
+            reflection: false,
+            scale: 1.0,
+        }
+    }
+
+    /// Calculate the minimal RMSD of `frame` with the reference structure,
+    /// without constructing the rotation matrix unless `allow_scaling` is
+    /// set.
+    pub fn min_rmsd(&self, frame: &[Point3]) -> Result<f64> {
+        if frame.len() != self.inner.len() {
+            bail!("points size mismatch!");
+        }
+
+        if self.allow_scaling {
+            // the optimal scale factor depends on the rotation matrix
+            return Ok(self.fit(frame)?.rmsd);
+        }
+        let rmsd = self.inner.calc_rmsd(frame);
+        if !self.allow_reflection {
+            return Ok(rmsd);
+        }
+        let mut mirrored = frame.to_vec();
+        mirrored.mirror_invert();
+        Ok(rmsd.min(self.inner.calc_rmsd(&mirrored)))
+    }
+}
+// 80d2a6f9 ends here
+
+// [[file:../../gchemol-geometry.note::3e6fa0d2][3e6fa0d2]]
+#[test]
+fn test_prepared_reference() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let prepared = PreparedReference::new(&reference, Some(&weights)).unwrap();
+
+    let frames = [candidate.clone(), reference.clone()];
+    let sps: Vec<_> = frames.iter().map(|f| prepared.fit(f)).collect::<Result<_>>().unwrap();
+    let sp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    assert_relative_eq!(sps[0].rmsd, sp.rmsd, epsilon = 1e-8);
+    assert_relative_eq!(sps[0].rotation_matrix, sp.rotation_matrix, epsilon = 1e-8);
+    assert_relative_eq!(sps[0].translation, sp.translation, epsilon = 1e-8);
+    assert_relative_eq!(sps[1].rmsd, 0.0, epsilon = 1e-6);
+
+    assert_relative_eq!(prepared.min_rmsd(&candidate).unwrap(), sp.rmsd, epsilon = 1e-8);
+    assert!(prepared.fit(&candidate[1..]).is_err());
+
+    // the same options as Superpose
+    let mut enantiomer = candidate.clone();
+    enantiomer.point_invert();
+    for (allow_reflection, allow_scaling) in [(true, false), (false, true), (true, true)] {
+        let mut prepared = prepared.clone();
+        prepared.allow_reflection = allow_reflection;
+        prepared.allow_scaling = allow_scaling;
+        let mut sup = Superpose::new(&enantiomer);
+        sup.allow_reflection = allow_reflection;
+        sup.allow_scaling = allow_scaling;
+        let sp = sup.onto(&reference, Some(&weights));
+        let sp_prepared = prepared.fit(&enantiomer).unwrap();
+        assert_eq!(sp_prepared.reflection, sp.reflection);
+        assert_relative_eq!(sp_prepared.scale, sp.scale, epsilon = 1e-8);
+        assert_relative_eq!(sp_prepared.rmsd, sp.rmsd, epsilon = 1e-8);
+        assert_relative_eq!(prepared.min_rmsd(&enantiomer).unwrap(), sp.rmsd, epsilon = 1e-8);
+    }
+}
+// 3e6fa0d2 ends here
@@ -96,71 +96,6 @@ use super::*;
 // header:1 ends here
 
 // [[file:../../gchemol-geometry.note::*core][core:1]]
-/// Calculate the inner product of two structures.
-///
-/// Parameters
-/// ----------
-/// coords1: reference structure
-/// center1: weighted center of reference structure
-/// coords2: candidate structure
-/// center2: weighted center of candidate structure
-/// weight : the weight array
-///
-/// Notes
-/// -----
-/// 1. Coordinates are stored as Nx3 arrays instead of 3xN as in original implementation
-/// 2. Will *panic* if the length of weights is smaller than the coordinates
-///
-/// Return
-/// ------
-/// (arr_a, e0): inner product array and e0 (inputs for fast_calc_rmsd_and_rotation)
-///
-fn inner_product(coords1: &[[f64; 3]], center1: &[f64; 3], coords2: &[[f64; 3]], center2: &[f64; 3], weights: &[f64]) -> ([f64; 9], f64) {
-    let natoms = coords1.len();
-    debug_assert!(natoms == coords2.len());
-
-    let mut mat_a = [0.0; 9];
-
-    // inner product
-    let mut g1 = 0.0;
-    let mut g2 = 0.0;
-    for i in 0..natoms {
-        // take the weight if any, or set it to 1.0
-        // let wi = weights.map_or_else(|| 1.0, |v| v[i]);
-        let wi = weights[i];
-
-        let cx1 = coords1[i][0] - center1[0];
-        let cy1 = coords1[i][1] - center1[1];
-        let cz1 = coords1[i][2] - center1[2];
-
-        let x1 = wi * cx1;
-        let y1 = wi * cy1;
-        let z1 = wi * cz1;
-
-        g1 += x1 * cx1 + y1 * cy1 + z1 * cz1;
-
-        let x2 = coords2[i][0] - center2[0];
-        let y2 = coords2[i][1] - center2[1];
-        let z2 = coords2[i][2] - center2[2];
-
-        g2 += wi * (x2.powi(2) + y2.powi(2) + z2.powi(2));
-
-        mat_a[0] += x1 * x2;
-        mat_a[1] += x1 * y2;
-        mat_a[2] += x1 * z2;
-
-        mat_a[3] += y1 * x2;
-        mat_a[4] += y1 * y2;
-        mat_a[5] += y1 * z2;
-
-        mat_a[6] += z1 * x2;
-        mat_a[7] += z1 * y2;
-        mat_a[8] += z1 * z2;
-    }
-
-    (mat_a, (g1 + g2) * 0.5)
-}
-
 // Calculate the RMSD, and/or the optimal rotation matrix.
 //
 //        Input:
@@ -390,6 +325,142 @@ fn get_center_of_coords(coords: &[[f64; 3]], weights: &[f64]) -> [f64; 3] {
     [xsum, ysum, zsum]
 }
 
+/// Return the weights used in QCP calculation for `n` points, which are set
+/// to 1.0 if `weights` is None.
+fn prepare_weights(weights: Option<&[f64]>, n: usize) -> Vec<f64> {
+    if let Some(w) = weights {
+        debug_assert_eq!(w.len(), n, "invalid number of weights!");
+        let wsum: f64 = w.iter().sum();
+        // HACK: if weights are too small, QCP iteration will produce NaN floats
+        // for noises.
+        if wsum < 1.0 {
+            w.iter().map(|x| x / wsum).collect()
+        } else {
+            w.to_vec()
+        }
+    } else {
+        vec![1.0; n]
+    }
+}
+
+/// Return coordinates translated to their weighted center.
+fn center_coords(coords: &[[f64; 3]], weights: &[f64]) -> Vec<[f64; 3]> {
+    let [cx, cy, cz] = get_center_of_coords(coords, weights);
+    coords.iter().map(|p| [p[0] - cx, p[1] - cy, p[2] - cz]).collect()
+}
+
+/// Calculate only the minimal RMSD between two structures, skipping the
+/// construction of the rotation matrix.
+pub(super) fn calc_rmsd(coords1: &[[f64; 3]], coords2: &[[f64; 3]], weights: Option<&[f64]>) -> f64 {
+    QcpReference::new(coords1, weights).calc_rmsd(coords2)
+}
+
+/// The reference structure prepared once for repeated superpositions, with
+/// its weighted center, weights and self inner product precomputed.
+#[derive(Clone, Debug)]
+pub(super) struct QcpReference {
+    /// the coordinates translated to the weighted center
+    coords: Vec<[f64; 3]>,
+    center: [f64; 3],
+    weights: Vec<f64>,
+    wsum: f64,
+    /// the weighted self inner product
+    g1: f64,
+}
+
+impl QcpReference {
+    pub(super) fn new(coords: &[[f64; 3]], weights: Option<&[f64]>) -> Self {
+        let weights = prepare_weights(weights, coords.len());
+        let wsum: f64 = weights.iter().sum();
+        let center = get_center_of_coords(coords, &weights);
+        let coords = center_coords(coords, &weights);
+        let g1 = coords
+            .iter()
+            .zip(&weights)
+            .map(|(p, w)| w * (p[0].powi(2) + p[1].powi(2) + p[2].powi(2)))
+            .sum();
+
+        Self {
+            coords,
+            center,
+            weights,
+            wsum,
+            g1,
+        }
+    }
+
+    /// Return the number of points.
+    pub(super) fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// Calculate the inner product with candidate structure `coords2`, and
+    /// return the weighted center of `coords2` as well.
+    fn inner_product(&self, coords2: &[[f64; 3]]) -> ([f64; 9], f64, [f64; 3]) {
+        debug_assert_eq!(self.len(), coords2.len());
+
+        let center2 = get_center_of_coords(coords2, &self.weights);
+        let mut mat_a = [0.0; 9];
+        let mut g2 = 0.0;
+        for (i, &[x1, y1, z1]) in self.coords.iter().enumerate() {
+            let wi = self.weights[i];
+            let (x1, y1, z1) = (wi * x1, wi * y1, wi * z1);
+
+            let x2 = coords2[i][0] - center2[0];
+            let y2 = coords2[i][1] - center2[1];
+            let z2 = coords2[i][2] - center2[2];
+            g2 += wi * (x2.powi(2) + y2.powi(2) + z2.powi(2));
+
+            mat_a[0] += x1 * x2;
+            mat_a[1] += x1 * y2;
+            mat_a[2] += x1 * z2;
+
+            mat_a[3] += y1 * x2;
+            mat_a[4] += y1 * y2;
+            mat_a[5] += y1 * z2;
+
+            mat_a[6] += z1 * x2;
+            mat_a[7] += z1 * y2;
+            mat_a[8] += z1 * z2;
+        }
+
+        (mat_a, (self.g1 + g2) * 0.5, center2)
+    }
+
+    /// Calculate only the minimal RMSD of candidate structure `coords2`.
+    pub(super) fn calc_rmsd(&self, coords2: &[[f64; 3]]) -> f64 {
+        let (mat_a, e0, _) = self.inner_product(coords2);
+        // a positive min_score larger than any rmsd skips the rotation matrix
+        let (rmsd, _) = fast_calc_rmsd_and_rotation(&mat_a, e0, self.wsum, f64::INFINITY);
+        rmsd
+    }
+
+    /// Calculate the RMSD & rotational matrix for superposing `coords2` onto
+    /// the reference.
+    pub(super) fn calc_rmsd_rotational_matrix(&self, coords2: &[[f64; 3]]) -> (f64, [f64; 3], Option<[f64; 9]>) {
+        let (mat_a, e0, center2) = self.inner_product(coords2);
+
+        // calculate the RMSD & rotational matrix
+        let (rmsd, rot) = fast_calc_rmsd_and_rotation(&mat_a, e0, self.wsum, -1.0);
+
+        // rotated center2
+        let rotc = if let Some(r) = rot {
+            [
+                r[0] * center2[0] + r[1] * center2[1] + r[2] * center2[2],
+                r[3] * center2[0] + r[4] * center2[1] + r[5] * center2[2],
+                r[6] * center2[0] + r[7] * center2[1] + r[8] * center2[2],
+            ]
+        } else {
+            center2
+        };
+
+        let center1 = self.center;
+        let translation = [center1[0] - rotc[0], center1[1] - rotc[1], center1[2] - rotc[2]];
+
+        (rmsd, translation, rot)
+    }
+}
+
 // Calculate the RMSD & rotational matrix.
 //
 // Input:
@@ -407,47 +478,7 @@ pub(super) fn calc_rmsd_rotational_matrix(
     coords2: &[[f64; 3]],
     weights: Option<&[f64]>,
 ) -> (f64, [f64; 3], Option<[f64; 9]>) {
-    // the sum of weights
-    let n = coords1.len();
-    let weights = if let Some(w) = weights {
-        debug_assert_eq!(w.len(), n, "invalid number of weights!");
-        let wsum: f64 = w.iter().sum();
-        // HACK: if weights are too small, QCP iteration will produce NaN floats
-        // for noises.
-        if wsum < 1.0 {
-            w.iter().map(|x| x / wsum).collect()
-        } else {
-            w.to_vec()
-        }
-    } else {
-        vec![1.0; n]
-    };
-    let wsum: f64 = weights.iter().sum();
-
-    // calculate the centers of the structures
-    let center1 = get_center_of_coords(coords1, &weights);
-    let center2 = get_center_of_coords(coords2, &weights);
-
-    // calculate the (weighted) inner product of two structures
-    let (mat_a, e0) = inner_product(&coords1, &center1, &coords2, &center2, &weights);
-
-    // calculate the RMSD & rotational matrix
-    let (rmsd, rot) = fast_calc_rmsd_and_rotation(&mat_a, e0, wsum, -1.0);
-
-    // rotated center2
-    let rotc = if let Some(r) = rot {
-        [
-            r[0] * center2[0] + r[1] * center2[1] + r[2] * center2[2],
-            r[3] * center2[0] + r[4] * center2[1] + r[5] * center2[2],
-            r[6] * center2[0] + r[7] * center2[1] + r[8] * center2[2],
-        ]
-    } else {
-        center2.clone()
-    };
-
-    let translation = [center1[0] - rotc[0], center1[1] - rotc[1], center1[2] - rotc[2]];
-
-    (rmsd, translation, rot)
+    QcpReference::new(coords1, weights).calc_rmsd_rotational_matrix(coords2)
 }
 // core:1 ends here
 
@@ -0,0 +1,104 @@
+// [[file:../../gchemol-geometry.note::b7e3a04d][b7e3a04d]]
+use super::*;
+// b7e3a04d ends here
+
+// [[file:../../gchemol-geometry.note::61f2c9d8][61f2c9d8]]
+/// Per-point deviations of a superimposed structure from the reference.
+#[derive(Clone, Debug)]
+pub struct DeviationReport {
+    /// The displacement vector of each superimposed point from the reference
+    pub displacements: Vec<Point3>,
+
+    /// The distance of each superimposed point from the reference
+    pub distances: Vec<f64>,
+
+    /// The largest distance
+    pub max_deviation: f64,
+
+    /// The index of the point having the largest distance
+    pub max_index: usize,
+
+    /// RMSD with all points equally weighted
+    pub rmsd: f64,
+
+    /// RMSD with the weight of each point
+    pub weighted_rmsd: f64,
+
+    /// The mean of all distances
+    pub mean_absolute_deviation: f64,
+}
+
+impl Superposition {
+    /// Return a report of deviations of `candidate` points from `reference`
+    /// after applying the superposition.
+    ///
+    /// # Panics
+    ///
+    /// * panics if there is no point, or if sizes of `candidate`,
+    ///   `reference` and `weights` differ.
+    pub fn deviation_report(&self, candidate: &[Point3], reference: &[Point3], weights: Option<&[f64]>) -> DeviationReport {
+        let npts = candidate.len();
+        assert!(npts > 0, "no point for deviation report!");
+        assert_eq!(npts, reference.len(), "points size mismatch!");
+        if let Some(w) = weights {
+            assert_eq!(npts, w.len(), "weights size mismatch!");
+        }
+
+        let displacements: Vec<Point3> = self
+            .apply(candidate)
+            .iter()
+            .zip(reference)
+            .map(|(p, r)| [p[0] - r[0], p[1] - r[1], p[2] - r[2]])
+            .collect();
+        let distances: Vec<f64> = displacements.iter().map(|&d| Vector3f::from(d).norm()).collect();
+        let (max_index, max_deviation) = distances
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("deviation report");
+
+        let msd = distances.iter().map(|d| d * d).sum::<f64>() / npts as f64;
+        let weighted_rmsd = match weights {
+            Some(w) => {
+                let wsum: f64 = w.iter().sum();
+                (distances.iter().zip(w).map(|(d, wi)| wi * d * d).sum::<f64>() / wsum).sqrt()
+            }
+            None => msd.sqrt(),
+        };
+        let mean_absolute_deviation = distances.iter().sum::<f64>() / npts as f64;
+
+        DeviationReport {
+            displacements,
+            distances,
+            max_deviation,
+            max_index,
+            rmsd: msd.sqrt(),
+            weighted_rmsd,
+            mean_absolute_deviation,
+        }
+    }
+}
+// 61f2c9d8 ends here
+
+// [[file:../../gchemol-geometry.note::0a4d8e73][0a4d8e73]]
+#[test]
+fn test_deviation_report() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    let report = sp.deviation_report(&candidate, &reference, Some(&weights));
+    assert_relative_eq!(report.weighted_rmsd, sp.rmsd, epsilon = 1e-6);
+
+    let sp = Superpose::new(&candidate).onto(&reference, None);
+    let report = sp.deviation_report(&candidate, &reference, None);
+    assert_relative_eq!(report.rmsd, sp.rmsd, epsilon = 1e-6);
+    assert_relative_eq!(report.weighted_rmsd, report.rmsd, epsilon = 1e-12);
+    assert_eq!(report.distances.len(), candidate.len());
+    assert_relative_eq!(report.distances[2], Vector3f::from(report.displacements[2]).norm(), epsilon = 1e-12);
+    assert_relative_eq!(report.max_deviation, report.distances[report.max_index], epsilon = 1e-12);
+    assert!(report.distances.iter().all(|&d| d <= report.max_deviation));
+    assert!(report.mean_absolute_deviation <= report.rmsd);
+}
+// 0a4d8e73 ends here
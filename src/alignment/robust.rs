@@ -0,0 +1,248 @@
+// [[file:../../gchemol-geometry.note::a4c58e21][a4c58e21]]
+use super::*;
+// a4c58e21 ends here
+
+// [[file:../../gchemol-geometry.note::0f9d6b7e][0f9d6b7e]]
+/// The criterion for selecting core points in `Superpose::onto_core`.
+#[derive(Clone, Copy, Debug)]
+pub enum CoreSelection {
+    /// Keep points deviating no more than the cutoff distance after fitting.
+    Cutoff(f64),
+    /// Keep the best-fitting fraction of points.
+    Fraction(f64),
+}
+
+/// The minimum number of points required for a meaningful superposition.
+const MIN_CORE_POINTS: usize = 3;
+
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure using only the
+    /// rigid core. Points with large deviations are iteratively excluded from
+    /// the fit until the core set no longer changes.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point, or of each selected point if
+    ///   `with_selection` is used
+    /// * selection: the criterion for keeping core points
+    ///
+    /// Return
+    /// ------
+    /// The superposition fitted on the core, and the indices of core points
+    /// among fitted points, i.e. the positions in selected indices if
+    /// `with_selection` is used.
+    pub fn onto_core(
+        &mut self,
+        reference: &[Point3],
+        weights: Option<&[f64]>,
+        selection: CoreSelection,
+    ) -> Result<(Superposition, Vec<usize>)> {
+        let (candidate, reference) = self.fitted_points(reference)?;
+        let npts = candidate.len();
+        if reference.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if weights.is_some_and(|w| w.len() != npts) {
+            bail!("weights size mismatch!");
+        }
+        if npts < MIN_CORE_POINTS {
+            bail!("too few points for core superposition: {}", npts);
+        }
+        match selection {
+            CoreSelection::Cutoff(d) if d <= 0.0 => bail!("invalid cutoff distance: {}", d),
+            CoreSelection::Fraction(f) if f <= 0.0 || f > 1.0 => bail!("invalid core fraction: {}", f),
+            _ => {}
+        }
+
+        // fit the selected points only
+        let mut sup = Superpose {
+            positions: &candidate,
+            selection: None,
+            ..*self
+        };
+        let mut core: Vec<usize> = (0..npts).collect();
+        for _ in 0..100 {
+            // exclude points outside the core by zero weights
+            let mut core_weights = vec![0.0; npts];
+            for &i in &core {
+                core_weights[i] = weights.map_or(1.0, |w| w[i]);
+            }
+            let sp = sup.try_onto(&reference, Some(&core_weights))?;
+
+            // rank points by deviations after fitting
+            let devs = sp.deviation_report(&candidate, &reference, None).distances;
+            let mut ranked: Vec<usize> = (0..npts).collect();
+            ranked.sort_by(|&i, &j| devs[i].total_cmp(&devs[j]));
+            let n = match selection {
+                CoreSelection::Cutoff(d) => devs.iter().filter(|&&x| x <= d).count(),
+                CoreSelection::Fraction(f) => (f * npts as f64).ceil() as usize,
+            };
+            let mut new_core = ranked[..n.max(MIN_CORE_POINTS)].to_vec();
+            new_core.sort_unstable();
+
+            if new_core == core {
+                return Ok((sp, core));
+            }
+            core = new_core;
+        }
+
+        bail!("core superposition does not converge!")
+    }
+}
+// 0f9d6b7e ends here
+
+// [[file:../../gchemol-geometry.note::e2b91f54][e2b91f54]]
+impl<'a> Superpose<'a> {
+    /// Superpose candidate structure onto reference structure with Gaussian
+    /// weights, which are iteratively updated as w_i = exp(-d_i^2 / sigma^2)
+    /// from the current deviations d_i until convergence (the Damm-Carlson
+    /// scheme). Rigid regions are fitted without selecting a subset by hand.
+    ///
+    /// The fit honors the options of `Superpose`, such as the algorithm,
+    /// reflection, scaling and selection.
+    ///
+    /// Parameters
+    /// ----------
+    /// * reference: reference coordinates
+    /// * weights  : weight of each point, or of each selected point if
+    ///   `with_selection` is used, which is combined with Gaussian weights
+    /// * sigma    : the width of the Gaussian weighting function
+    ///
+    /// Return
+    /// ------
+    /// The superposition with the weighted RMSD, and the final Gaussian
+    /// weights of fitted points.
+    pub fn onto_gaussian_weighted(
+        &mut self,
+        reference: &[Point3],
+        weights: Option<&[f64]>,
+        sigma: f64,
+    ) -> Result<(Superposition, Vec<f64>)> {
+        let (candidate, reference) = self.fitted_points(reference)?;
+        let npts = candidate.len();
+        if reference.len() != npts {
+            bail!("points size mismatch!");
+        }
+        if weights.is_some_and(|w| w.len() != npts) {
+            bail!("weights size mismatch!");
+        }
+        if sigma <= 0.0 {
+            bail!("invalid sigma for Gaussian weighting: {}", sigma);
+        }
+
+        // fit the selected points only
+        let mut sup = Superpose {
+            positions: &candidate,
+            selection: None,
+            ..*self
+        };
+        let sigma2 = sigma * sigma;
+        let mut gaussian_weights = vec![1.0; npts];
+        for _ in 0..200 {
+            let ws: Vec<_> = (0..npts)
+                .map(|i| gaussian_weights[i] * weights.map_or(1.0, |w| w[i]))
+                .collect();
+            if ws.iter().sum::<f64>() <= 0.0 {
+                bail!("Gaussian weights vanish: sigma {} is too small!", sigma);
+            }
+            let sp = sup.try_onto(&reference, Some(&ws))?;
+
+            // update weights from the current deviations
+            let devs = sp.deviation_report(&candidate, &reference, None).distances;
+            let new_weights: Vec<_> = devs.iter().map(|d| (-d * d / sigma2).exp()).collect();
+            let dw = new_weights.iter().zip(&gaussian_weights).map(|(a, b)| (a - b).abs()).float_max();
+            gaussian_weights = new_weights;
+            if dw < 1e-8 {
+                return Ok((sp, gaussian_weights));
+            }
+        }
+
+        bail!("Gaussian-weighted superposition does not converge!")
+    }
+}
+// e2b91f54 ends here
+
+// [[file:../../gchemol-geometry.note::7c2e4a90][7c2e4a90]]
+#[test]
+fn test_superpose_core() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, _, _) = super::qcprot::prepare_test_data();
+    let t = RigidTransform::from_axis_angle([-0.3, 0.8, 0.5], 2.4) * RigidTransform::from_translation([0.5, 3.0, -1.0]);
+    let mut candidate = t.apply(&reference);
+    // two floppy points
+    candidate[1][2] += 1.8;
+    candidate[4][0] -= 2.2;
+
+    let sp = Superpose::new(&candidate).onto(&reference, None);
+    assert!(sp.rmsd > 0.5);
+
+    let (sp, core) = Superpose::new(&candidate)
+        .onto_core(&reference, None, CoreSelection::Cutoff(0.5))
+        .unwrap();
+    assert_eq!(core, vec![0, 2, 3, 5, 6]);
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+
+    let (_, core) = Superpose::new(&candidate)
+        .onto_core(&reference, None, CoreSelection::Fraction(0.7))
+        .unwrap();
+    assert_eq!(core, vec![0, 2, 3, 5, 6]);
+
+    // core among selected points
+    let selection = [1, 2, 3, 4, 5, 6];
+    let (sp, core) = Superpose::new(&candidate)
+        .with_selection(&selection, &selection)
+        .onto_core(&reference, Some(&[1.0; 6]), CoreSelection::Cutoff(0.5))
+        .unwrap();
+    assert_eq!(core, vec![1, 2, 4, 5]);
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+
+    // degenerate core is an error rather than a panic
+    let coincident = vec![[1.0, 2.0, 3.0]; 7];
+    assert!(Superpose::new(&coincident)
+        .onto_core(&reference, None, CoreSelection::Cutoff(0.5))
+        .is_err());
+}
+// 7c2e4a90 ends here
+
+// [[file:../../gchemol-geometry.note::3f6b9d28][3f6b9d28]]
+#[test]
+fn test_superpose_gaussian_weighted() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, _, _) = super::qcprot::prepare_test_data();
+    let t = RigidTransform::from_axis_angle([0.9, -0.2, 0.4], -0.9) * RigidTransform::from_translation([-1.5, -0.5, 2.0]);
+    let mut candidate = t.apply(&reference);
+    // a swinging terminal point
+    candidate[6] = [candidate[6][0] + 1.2, candidate[6][1] - 1.0, candidate[6][2] + 1.5];
+
+    // Gaussian weighting suppresses the swinging point
+    let (sp, ws) = Superpose::new(&candidate)
+        .onto_gaussian_weighted(&reference, None, 0.5)
+        .unwrap();
+    assert!(ws[6] < 1e-3);
+    assert!(ws[0] > 0.99);
+    assert!(sp.rmsd < 1e-2);
+
+    // the options of superposition are honored
+    let mut enantiomer = candidate.clone();
+    enantiomer.mirror_invert();
+    let mut sup = Superpose::new(&enantiomer);
+    sup.algorithm = SuperpositionAlgo::Kabsch;
+    sup.allow_reflection = true;
+    let (sp_mirrored, _) = sup.onto_gaussian_weighted(&reference, None, 0.5).unwrap();
+    assert!(sp_mirrored.reflection);
+    assert_relative_eq!(sp_mirrored.rmsd, sp.rmsd, epsilon = 1e-6);
+
+    // fit on selected points, with weights of selected points
+    let selection = [2, 3, 4, 5, 6];
+    let (sp, ws) = Superpose::new(&candidate)
+        .with_selection(&selection, &selection)
+        .onto_gaussian_weighted(&reference, Some(&[1.0; 5]), 0.5)
+        .unwrap();
+    assert_eq!(ws.len(), 5);
+    assert!(ws[4] < 1e-3 && ws[0] > 0.99);
+    assert!(sp.rmsd < 1e-2);
+}
+// 3f6b9d28 ends here
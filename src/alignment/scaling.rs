@@ -0,0 +1,82 @@
+// [[file:../../gchemol-geometry.note::5c0b7e93][5c0b7e93]]
+use super::*;
+// 5c0b7e93 ends here
+
+// [[file:../../gchemol-geometry.note::d84a1f26][d84a1f26]]
+impl Superposition {
+    /// Solve for the optimal isotropic scale factor with the rotation held
+    /// fixed, and update translation and RMSD accordingly.
+    ///
+    /// # Reference
+    ///
+    /// Umeyama, S. IEEE Trans. Pattern Anal. Mach. Intell. 1991, 13, 376–380.
+    pub(super) fn rescale(&mut self, candidate: &[Point3], reference: &[Point3], weights: Option<&[f64]>) {
+        let npts = candidate.len();
+        let weights = weights.map_or_else(|| vec![1.0; npts], |w| w.to_vec());
+        let center_can = Vector3f::from(crate::base::weighted_center_of_geometry(candidate, &weights));
+        let center_ref = Vector3f::from(crate::base::weighted_center_of_geometry(reference, &weights));
+
+        // s = sum w_i (R x_i) . y_i / sum w_i |x_i|^2 for centered coordinates
+        let mut sxy = 0.0;
+        let mut sxx = 0.0;
+        for i in 0..npts {
+            let x = self.rotation_matrix * (Vector3f::from(candidate[i]) - center_can);
+            let y = Vector3f::from(reference[i]) - center_ref;
+            sxy += weights[i] * x.dot(&y);
+            sxx += weights[i] * x.norm_squared();
+        }
+        self.scale = if sxx > 0.0 && sxy > 0.0 { sxy / sxx } else { 1.0 };
+        self.translation = center_ref - self.scale * (self.rotation_matrix * center_can);
+
+        let wsum: f64 = weights.iter().sum();
+        let msd: f64 = self
+            .apply(candidate)
+            .iter()
+            .zip(reference)
+            .zip(&weights)
+            .map(|((p, r), w)| w * p.vecdist_squared(r))
+            .sum::<f64>()
+            / wsum;
+        self.rmsd = msd.sqrt();
+    }
+}
+// d84a1f26 ends here
+
+// [[file:../../gchemol-geometry.note::8e3f20c7][8e3f20c7]]
+#[test]
+fn test_superpose_scaling() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+    let sp_rigid = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    assert_eq!(sp_rigid.scale, 1.0);
+
+    // the candidate: scaled, rotated and translated reference
+    let t = RigidTransform::from_axis_angle([1.0, 1.0, -0.3], 0.7);
+    let scaled: Vec<Point3> = reference
+        .iter()
+        .map(|&p| (t.rotation * Vector3f::from(p) * 1.6 + Vector3f::new(2.0, -1.0, 0.5)).into())
+        .collect();
+    let mut sup = Superpose::new(&scaled);
+    sup.allow_scaling = true;
+    let sp = sup.onto(&reference, Some(&weights));
+    assert_relative_eq!(sp.scale, 0.625, epsilon = 1e-8);
+    assert_relative_eq!(sp.rmsd, 0.0, epsilon = 1e-6);
+    let superimposed = sp.apply(&scaled);
+    for (p, r) in superimposed.iter().zip(&reference) {
+        assert_relative_eq!(Vector3f::from(*p), Vector3f::from(*r), epsilon = 1e-6);
+    }
+    // not representable by a rigid-body transformation
+    assert!(sp.transform().is_err());
+    assert!(RigidTransform::try_from(&sp).is_err());
+    assert!(RigidTransform::try_from(&sp_rigid).is_ok());
+
+    // scaling never makes RMSD worse
+    let mut sup = Superpose::new(&candidate);
+    sup.allow_scaling = true;
+    let sp = sup.onto(&reference, Some(&weights));
+    assert!(sp.rmsd <= sp_rigid.rmsd + 1e-12);
+    let report = sp.deviation_report(&candidate, &reference, Some(&weights));
+    assert_relative_eq!(report.weighted_rmsd, sp.rmsd, epsilon = 1e-8);
+}
+// 8e3f20c7 ends here
@@ -0,0 +1,107 @@
+// [[file:../../gchemol-geometry.note::9d2e4b61][9d2e4b61]]
+use super::*;
+
+use std::borrow::Cow;
+// 9d2e4b61 ends here
+
+// [[file:../../gchemol-geometry.note::c3a7f058][c3a7f058]]
+/// The candidate and reference points used for fitting.
+type FittedPoints<'a, 'b> = (Cow<'a, [Point3]>, Cow<'b, [Point3]>);
+
+/// Return points at `indices`, or None if any index is out of range.
+fn select_points(points: &[Point3], indices: &[usize]) -> Option<Vec<Point3>> {
+    indices.iter().map(|&i| points.get(i).copied()).collect()
+}
+
+impl<'a> Superpose<'a> {
+    /// Fit only the selected points: the i-th candidate point in
+    /// `candidate_indices` is paired with the i-th reference point in
+    /// `reference_indices`. The resulting superposition still applies to the
+    /// whole candidate structure.
+    ///
+    /// Weights passed to `onto`, `try_onto`, `min_rmsd` and other methods
+    /// fitting point pairs are then given for each selected pair. Methods
+    /// finding the point correspondence by themselves, such as
+    /// `onto_permuted` and `onto_icp`, return `SuperposeError::InvalidSelection`.
+    pub fn with_selection(mut self, candidate_indices: &'a [usize], reference_indices: &'a [usize]) -> Self {
+        self.selection = Some((candidate_indices, reference_indices));
+        self
+    }
+
+    /// Return the candidate and reference points used for fitting.
+    pub(super) fn fitted_points<'b>(&self, reference: &'b [Point3]) -> std::result::Result<FittedPoints<'a, 'b>, SuperposeError> {
+        match self.selection {
+            None => Ok((Cow::Borrowed(self.positions), Cow::Borrowed(reference))),
+            Some((icandidate, ireference)) => {
+                if icandidate.len() != ireference.len() {
+                    return Err(SuperposeError::InvalidSelection);
+                }
+                let candidate = select_points(self.positions, icandidate).ok_or(SuperposeError::InvalidSelection)?;
+                let reference = select_points(reference, ireference).ok_or(SuperposeError::InvalidSelection)?;
+                Ok((Cow::Owned(candidate), Cow::Owned(reference)))
+            }
+        }
+    }
+
+    /// Return an error if a selection is set, for methods establishing the
+    /// point correspondence by themselves.
+    pub(super) fn reject_selection(&self) -> std::result::Result<(), SuperposeError> {
+        match self.selection {
+            Some(_) => Err(SuperposeError::InvalidSelection),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the deviation of each fitted point pair after applying
+    /// superposition `sp`, in the order of the selected indices (or of all
+    /// points if no selection is set).
+    pub fn deviations(&self, sp: &Superposition, reference: &[Point3]) -> Result<Vec<f64>> {
+        let (candidate, reference) = self.fitted_points(reference)?;
+        if candidate.len() != reference.len() {
+            bail!("points size mismatch!");
+        }
+        if candidate.is_empty() {
+            return Ok(vec![]);
+        }
+
+        Ok(sp.deviation_report(&candidate, &reference, None).distances)
+    }
+}
+// c3a7f058 ends here
+
+// [[file:../../gchemol-geometry.note::5f81be2a][5f81be2a]]
+#[test]
+fn test_superpose_selection() {
+    use vecfx::approx::assert_relative_eq;
+
+    let (reference, candidate, weights) = qcprot::prepare_test_data();
+
+    // the reference with two extra points prepended, fitted on the original ones
+    let mut reference_ext = vec![[9.0, 9.0, 9.0], [-9.0, 0.0, 9.0]];
+    reference_ext.extend_from_slice(&reference);
+    let icandidate: Vec<usize> = (0..candidate.len()).collect();
+    let ireference: Vec<usize> = (2..reference_ext.len()).collect();
+
+    let sp_expected = Superpose::new(&candidate).onto(&reference, Some(&weights));
+    let mut sup = Superpose::new(&candidate).with_selection(&icandidate, &ireference);
+    let sp = sup.onto(&reference_ext, Some(&weights));
+    assert_relative_eq!(sp.rmsd, sp_expected.rmsd, epsilon = 1e-8);
+    assert_relative_eq!(sp.rotation_matrix, sp_expected.rotation_matrix, epsilon = 1e-8);
+    assert_relative_eq!(sup.min_rmsd(&reference_ext, Some(&weights)).unwrap(), sp.rmsd, epsilon = 1e-8);
+    let rmsd_expected = Superpose::new(&candidate).rmsd(&reference, Some(&weights)).unwrap();
+    assert_relative_eq!(sup.rmsd(&reference_ext, Some(&weights)).unwrap(), rmsd_expected, epsilon = 1e-8);
+    let sp = sup.try_onto(&reference_ext, Some(&weights)).unwrap();
+    assert_relative_eq!(sp.rmsd, sp_expected.rmsd, epsilon = 1e-8);
+
+    let deviations = sup.deviations(&sp, &reference_ext).unwrap();
+    assert_eq!(deviations.len(), candidate.len());
+    let superimposed = sp.apply(&candidate);
+    assert_relative_eq!(deviations[3], superimposed[3].vecdist(&reference[3]), epsilon = 1e-8);
+
+    // invalid selections
+    let e = Superpose::new(&candidate).with_selection(&[0, 1], &[0]).try_onto(&reference, None).unwrap_err();
+    assert_eq!(e, SuperposeError::InvalidSelection);
+    let e = Superpose::new(&candidate).with_selection(&[0, 99], &[0, 1]).try_onto(&reference, None).unwrap_err();
+    assert_eq!(e, SuperposeError::InvalidSelection);
+}
+// 5f81be2a ends here
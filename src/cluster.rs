@@ -0,0 +1,238 @@
+// [[file:../gchemol-geometry.note::6b2f8e14][6b2f8e14]]
+//! Clustering of conformers by superposition RMSD
+
+use super::*;
+use crate::alignment::{condensed_index, pairwise_rmsd_condensed};
+
+use std::collections::BTreeMap;
+// 6b2f8e14 ends here
+
+// [[file:../gchemol-geometry.note::93c7d0a5][93c7d0a5]]
+type Points = Vec<Coord3>;
+
+/// The result of clustering conformers.
+#[derive(Clone, Debug)]
+pub struct Clustering {
+    /// The cluster index of each conformer
+    pub labels: Vec<usize>,
+
+    /// The index of the centroid conformer of each cluster
+    pub centroids: Vec<usize>,
+}
+
+impl Clustering {
+    /// Return the number of clusters.
+    pub fn len(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Return true if there is no cluster.
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Return the indices of conformers in each cluster.
+    pub fn members(&self) -> Vec<Vec<usize>> {
+        let mut members = vec![vec![]; self.len()];
+        for (i, &c) in self.labels.iter().enumerate() {
+            members[c].push(i);
+        }
+        members
+    }
+}
+
+/// The criterion for measuring the distance between two clusters.
+#[derive(Clone, Copy, Debug)]
+pub enum Linkage {
+    /// The minimum distance between members of two clusters
+    Single,
+    /// The average distance between members of two clusters
+    Average,
+}
+
+/// Group conformers using Butina/Taylor sphere-exclusion clustering.
+/// Conformers having more neighbors within `cutoff` RMSD are picked as
+/// cluster centroids first, and their unassigned neighbors are excluded
+/// from being picked again.
+///
+/// Parameters
+/// ----------
+/// * conformers: the structures to be clustered, all of the same size
+/// * weights  : weight of each point for RMSD calculation
+/// * cutoff   : the RMSD threshold for neighbors
+pub fn butina(conformers: &[Points], weights: Option<&[f64]>, cutoff: f64) -> Result<Clustering> {
+    let n = conformers.len();
+    let dm = pairwise_rmsd_condensed(conformers, weights)?;
+    let dist = |i: usize, j: usize| if i < j { dm[condensed_index(n, i, j)] } else { dm[condensed_index(n, j, i)] };
+
+    let neighbors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| j != i && dist(i, j) <= cutoff).collect())
+        .collect();
+
+    // pick conformers with more neighbors first
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(neighbors[i].len()));
+
+    let mut labels = vec![usize::MAX; n];
+    let mut centroids = vec![];
+    for i in order {
+        if labels[i] != usize::MAX {
+            continue;
+        }
+        let c = centroids.len();
+        labels[i] = c;
+        for &j in &neighbors[i] {
+            if labels[j] == usize::MAX {
+                labels[j] = c;
+            }
+        }
+        centroids.push(i);
+    }
+
+    Ok(Clustering { labels, centroids })
+}
+
+/// Group conformers using agglomerative hierarchical clustering. Clusters
+/// are merged while their linkage distance is no more than `cutoff` RMSD.
+///
+/// The centroid of each cluster is the member with the minimum sum of RMSDs
+/// to other members (medoid).
+///
+/// Parameters
+/// ----------
+/// * conformers: the structures to be clustered, all of the same size
+/// * weights  : weight of each point for RMSD calculation
+/// * cutoff   : the RMSD threshold for merging clusters
+/// * linkage  : the criterion for the distance between clusters
+pub fn hierarchical(conformers: &[Points], weights: Option<&[f64]>, cutoff: f64, linkage: Linkage) -> Result<Clustering> {
+    let n = conformers.len();
+    let dm = pairwise_rmsd_condensed(conformers, weights)?;
+
+    // full distance matrix between clusters, updated on merging
+    let mut dist = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            dist[i][j] = dm[condensed_index(n, i, j)];
+            dist[j][i] = dist[i][j];
+        }
+    }
+
+    // nearest-neighbor chain algorithm, valid for single and average linkage
+    let mut size = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut chain: Vec<usize> = vec![];
+    for _ in 1..n {
+        if chain.is_empty() {
+            chain.push(active.iter().position(|&x| x).expect("active cluster"));
+        }
+        let (a, b) = loop {
+            let a = chain[chain.len() - 1];
+            let prev = if chain.len() >= 2 { Some(chain[chain.len() - 2]) } else { None };
+            // prefer the previous cluster on ties to avoid cycles
+            let mut b = prev.unwrap_or(usize::MAX);
+            let mut dmin = prev.map_or(f64::INFINITY, |p| dist[a][p]);
+            for k in (0..n).filter(|&k| active[k] && k != a) {
+                if dist[a][k] < dmin {
+                    dmin = dist[a][k];
+                    b = k;
+                }
+            }
+            if Some(b) == prev {
+                chain.truncate(chain.len() - 2);
+                break (a, b);
+            }
+            chain.push(b);
+        };
+
+        // merge b into a
+        let dab = dist[a][b];
+        for k in (0..n).filter(|&k| active[k] && k != a && k != b) {
+            let d = match linkage {
+                Linkage::Single => dist[a][k].min(dist[b][k]),
+                Linkage::Average => (size[a] as f64 * dist[a][k] + size[b] as f64 * dist[b][k]) / (size[a] + size[b]) as f64,
+            };
+            dist[a][k] = d;
+            dist[k][a] = d;
+        }
+        size[a] += size[b];
+        active[b] = false;
+        // both linkages are monotonic, so the flat clusters at the cutoff are
+        // formed by all merges within the cutoff
+        if dab <= cutoff {
+            parent[b] = a;
+        }
+    }
+
+    // collect flat clusters, larger clusters first
+    let root = |mut i: usize| {
+        while parent[i] != i {
+            i = parent[i];
+        }
+        i
+    };
+    let roots: Vec<usize> = (0..n).map(root).collect();
+    let mut root_members = BTreeMap::new();
+    for (i, &r) in roots.iter().enumerate() {
+        root_members.entry(r).or_insert_with(Vec::new).push(i);
+    }
+    let mut members: Vec<Vec<usize>> = root_members.into_values().collect();
+    members.sort_by_key(|m| (std::cmp::Reverse(m.len()), m[0]));
+
+    let mut labels = vec![0; n];
+    let mut centroids = vec![];
+    for (c, m) in members.iter().enumerate() {
+        for &i in m {
+            labels[i] = c;
+        }
+        let dsum = |i: usize| -> f64 {
+            m.iter()
+                .filter(|&&j| j != i)
+                .map(|&j| if i < j { dm[condensed_index(n, i, j)] } else { dm[condensed_index(n, j, i)] })
+                .sum()
+        };
+        let medoid = m.iter().copied().min_by(|&i, &j| dsum(i).total_cmp(&dsum(j))).expect("medoid");
+        centroids.push(medoid);
+    }
+
+    Ok(Clustering { labels, centroids })
+}
+// 93c7d0a5 ends here
+
+// [[file:../gchemol-geometry.note::e5a1c7b9][e5a1c7b9]]
+#[test]
+fn test_cluster_conformers() {
+    let reference = vec![
+        [-2.803, -15.373, 24.556],
+        [0.893, -16.062, 25.147],
+        [1.368, -12.371, 25.885],
+        [-1.651, -12.153, 28.177],
+        [-0.440, -15.218, 30.068],
+    ];
+
+    // three groups of conformers by distorting the first point
+    let mut conformers = vec![];
+    for &(dx, n) in &[(0.0, 4), (2.0, 3), (5.0, 1)] {
+        for k in 0..n {
+            let mut conf = reference.clone();
+            conf[0][0] += dx + 0.01 * k as f64;
+            conformers.push(conf);
+        }
+    }
+
+    let clustering = butina(&conformers, None, 0.3).unwrap();
+    assert_eq!(clustering.len(), 3);
+    assert_eq!(clustering.labels, vec![0, 0, 0, 0, 1, 1, 1, 2]);
+    assert_eq!(clustering.members()[1], vec![4, 5, 6]);
+
+    for linkage in [Linkage::Single, Linkage::Average] {
+        let clustering = hierarchical(&conformers, None, 0.3, linkage).unwrap();
+        assert_eq!(clustering.labels, vec![0, 0, 0, 0, 1, 1, 1, 2]);
+        assert_eq!(clustering.centroids[1], 5);
+        assert_eq!(clustering.centroids[2], 7);
+    }
+
+    let clustering = hierarchical(&conformers, None, 10.0, Linkage::Average).unwrap();
+    assert_eq!(clustering.len(), 1);
+}
+// e5a1c7b9 ends here
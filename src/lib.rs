@@ -32,10 +32,15 @@ use gut::prelude::*;
 // [[file:../gchemol-geometry.note::a70e28c8][a70e28c8]]
 mod alignment;
 mod base;
+mod rigid;
 mod traits;
 mod transform;
 
+pub mod cluster;
 pub mod random;
+pub mod shape;
+pub mod similarity;
+pub mod symmetry;
 // a70e28c8 ends here
 
 // [[file:../gchemol-geometry.note::62451bc9][62451bc9]]
@@ -44,6 +49,7 @@ pub type Coord3 = [f64; 3];
 
 pub use crate::alignment::*;
 pub use crate::base::*;
+pub use crate::rigid::*;
 
 #[cfg(feature = "adhoc")]
 pub use crate::transform::*;
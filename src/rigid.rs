@@ -0,0 +1,194 @@
+// [[file:../gchemol-geometry.note::2f6e0c84][2f6e0c84]]
+use super::*;
+use vecfx::*;
+
+use vecfx::nalgebra as na;
+// 2f6e0c84 ends here
+
+// [[file:../gchemol-geometry.note::a8d15e3b][a8d15e3b]]
+/// A rigid-body transformation applying a rotation followed by a
+/// translation: x' = R x + t.
+///
+/// The rotation matrix is orthogonal. It may be improper (det = -1) when
+/// obtained from a reflection-permitting superposition, in which case the
+/// conversions to axis-angle or unit quaternion are not available.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RigidTransform {
+    /// rotation matrix
+    pub rotation: Matrix3f,
+
+    /// translation vector
+    pub translation: Vector3f,
+}
+
+impl Default for RigidTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl RigidTransform {
+    /// Construct from rotation matrix and translation vector.
+    pub fn new(rotation: Matrix3f, translation: Vector3f) -> Self {
+        Self { rotation, translation }
+    }
+
+    /// The transformation doing nothing.
+    pub fn identity() -> Self {
+        Self::new(Matrix3f::identity(), Vector3f::zeros())
+    }
+
+    /// The pure translation by `t`.
+    pub fn from_translation(t: Coord3) -> Self {
+        Self::new(Matrix3f::identity(), t.into())
+    }
+
+    /// The pure rotation about `axis` (not necessarily normalized) by `angle`
+    /// in radian.
+    pub fn from_axis_angle(axis: Coord3, angle: f64) -> Self {
+        let axis = na::Unit::new_normalize(Vector3f::from(axis));
+        let r = na::Rotation3::from_axis_angle(&axis, angle);
+        Self::new(r.into_inner(), Vector3f::zeros())
+    }
+
+    /// The pure rotation represented by unit quaternion `q`.
+    pub fn from_unit_quaternion(q: &na::UnitQuaternion<f64>) -> Self {
+        Self::new(q.to_rotation_matrix().into_inner(), Vector3f::zeros())
+    }
+
+    /// Construct from a 4x4 homogeneous matrix.
+    pub fn from_homogeneous(m: &na::Matrix4<f64>) -> Result<Self> {
+        let bottom = m.fixed_slice::<1, 4>(3, 0);
+        if bottom.iter().zip([0.0, 0.0, 0.0, 1.0]).any(|(a, b)| (a - b).abs() > 1e-8) {
+            bail!("invalid homogeneous matrix: {}", m);
+        }
+        let rotation: Matrix3f = m.fixed_slice::<3, 3>(0, 0).into_owned();
+        if !(rotation.transpose() * rotation).relative_eq(&Matrix3f::identity(), 1e-6, 1e-6) {
+            bail!("not a rigid-body transformation: {}", m);
+        }
+        let translation: Vector3f = m.fixed_slice::<3, 1>(0, 3).into_owned();
+
+        Ok(Self::new(rotation, translation))
+    }
+
+    /// Return the 4x4 homogeneous matrix of the transformation.
+    pub fn to_homogeneous(&self) -> na::Matrix4<f64> {
+        let mut m = na::Matrix4::identity();
+        m.fixed_slice_mut::<3, 3>(0, 0).copy_from(&self.rotation);
+        m.fixed_slice_mut::<3, 1>(0, 3).copy_from(&self.translation);
+        m
+    }
+
+    /// Return true if the rotation is proper (det = +1).
+    pub fn is_proper(&self) -> bool {
+        self.rotation.determinant() > 0.0
+    }
+
+    /// Return the rotation as unit quaternion, or None if the rotation is
+    /// improper.
+    pub fn to_unit_quaternion(&self) -> Option<na::UnitQuaternion<f64>> {
+        if !self.is_proper() {
+            return None;
+        }
+        let r = na::Rotation3::from_matrix_unchecked(self.rotation);
+        Some(na::UnitQuaternion::from_rotation_matrix(&r))
+    }
+
+    /// Return the rotation as normalized axis and angle in radian, or None if
+    /// the rotation is improper. The axis is arbitrary for zero angle.
+    pub fn to_axis_angle(&self) -> Option<(Coord3, f64)> {
+        let q = self.to_unit_quaternion()?;
+        match q.axis_angle() {
+            Some((axis, angle)) => Some((axis.into_inner().into(), angle)),
+            None => Some(([0.0, 0.0, 1.0], 0.0)),
+        }
+    }
+
+    /// Return the inverse transformation.
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.transpose();
+        let translation = -(rotation * self.translation);
+        Self::new(rotation, translation)
+    }
+
+    /// Interpolate between `self` (t = 0) and `other` (t = 1), using
+    /// spherical linear interpolation for rotation and linear interpolation
+    /// for translation. Return None if any rotation is improper or the
+    /// interpolation path is undefined (rotations differ by 180 degree).
+    pub fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+        let q1 = self.to_unit_quaternion()?;
+        let q2 = other.to_unit_quaternion()?;
+        let q = q1.try_slerp(&q2, t, 1e-9)?;
+        let translation = self.translation.lerp(&other.translation, t);
+        Some(Self::new(q.to_rotation_matrix().into_inner(), translation))
+    }
+
+    /// Apply the transformation to point `p`.
+    pub fn apply_point(&self, p: Coord3) -> Coord3 {
+        (self.rotation * Vector3f::from(p) + self.translation).into()
+    }
+
+    /// Apply the transformation to all `points`.
+    pub fn apply(&self, points: &[Coord3]) -> Vec<Coord3> {
+        points.iter().map(|&p| self.apply_point(p)).collect()
+    }
+}
+
+/// Compose two transformations: `(a * b)` applies `b` first, then `a`.
+impl std::ops::Mul for RigidTransform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let rotation = self.rotation * rhs.rotation;
+        let translation = self.rotation * rhs.translation + self.translation;
+        Self::new(rotation, translation)
+    }
+}
+// a8d15e3b ends here
+
+// [[file:../gchemol-geometry.note::6dc93a1f][6dc93a1f]]
+#[test]
+fn test_rigid_transform() {
+    use approx::assert_relative_eq;
+    use std::f64::consts::PI;
+
+    let a = RigidTransform::from_axis_angle([0.0, 0.0, 1.0], PI / 2.0);
+    let b = RigidTransform::from_translation([1.0, 2.0, 3.0]);
+    let p = [1.0, 0.0, 0.0];
+
+    // compose
+    let ab = a * b;
+    assert_relative_eq!(Vector3f::from(ab.apply_point(p)), Vector3f::from(a.apply_point(b.apply_point(p))), epsilon = 1e-12);
+    assert_relative_eq!(Vector3f::from(ab.apply_point(p)), Vector3f::new(-2.0, 2.0, 3.0), epsilon = 1e-12);
+
+    // inverse
+    let ident = ab * ab.inverse();
+    assert_relative_eq!(ident.rotation, Matrix3f::identity(), epsilon = 1e-12);
+    assert_relative_eq!(ident.translation, Vector3f::zeros(), epsilon = 1e-12);
+
+    // homogeneous matrix
+    let m = ab.to_homogeneous();
+    let ab2 = RigidTransform::from_homogeneous(&m).unwrap();
+    assert_relative_eq!(ab2.rotation, ab.rotation, epsilon = 1e-12);
+    assert_relative_eq!(ab2.translation, ab.translation, epsilon = 1e-12);
+    assert!(RigidTransform::from_homogeneous(&(m * 2.0)).is_err());
+
+    // axis-angle and quaternion
+    let (axis, angle) = ab.to_axis_angle().unwrap();
+    assert_relative_eq!(Vector3f::from(axis), Vector3f::z(), epsilon = 1e-12);
+    assert_relative_eq!(angle, PI / 2.0, epsilon = 1e-12);
+    let q = ab.to_unit_quaternion().unwrap();
+    assert_relative_eq!(RigidTransform::from_unit_quaternion(&q).rotation, ab.rotation, epsilon = 1e-12);
+
+    // interpolation
+    let half = RigidTransform::identity().interpolate(&ab, 0.5).unwrap();
+    let (_, angle) = half.to_axis_angle().unwrap();
+    assert_relative_eq!(angle, PI / 4.0, epsilon = 1e-12);
+    assert_relative_eq!(half.translation, ab.translation * 0.5, epsilon = 1e-12);
+
+    // improper rotation
+    let mirror = RigidTransform::new(Matrix3f::from_diagonal(&Vector3f::new(1.0, 1.0, -1.0)), Vector3f::zeros());
+    assert!(!mirror.is_proper());
+    assert!(mirror.to_axis_angle().is_none());
+}
+// 6dc93a1f ends here
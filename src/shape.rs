@@ -0,0 +1,265 @@
+// [[file:../gchemol-geometry.note::c58f1e0a][c58f1e0a]]
+//! Shape comparison using Gaussian overlap volumes (ROCS-style)
+//!
+//! Overlap volumes are evaluated in the first-order approximation, i.e. as
+//! the sum of pairwise overlaps of atomic Gaussians. Higher-order terms of
+//! the inclusion-exclusion expansion are ignored, so the result is not the
+//! true volume overlap of hard spheres, and is overestimated for closely
+//! packed points.
+//!
+//! # Reference
+//!
+//! Grant, J. A.; Gallardo, M. A.; Pickup, B. T. J. Comput. Chem. 1996, 17, 1653–1666.
+
+use super::*;
+use crate::prelude::*;
+use vecfx::*;
+
+use std::f64::consts::PI;
+// c58f1e0a ends here
+
+// [[file:../gchemol-geometry.note::9e2d6b48][9e2d6b48]]
+/// The height of each atomic Gaussian, chosen to reproduce hard-sphere
+/// volumes of isolated atoms.
+const GAUSSIAN_HEIGHT: f64 = 2.0 * std::f64::consts::SQRT_2;
+
+/// A point set represented as a sum of spherical Gaussians.
+#[derive(Clone, Debug)]
+pub struct GaussianShape {
+    centers: Vec<Coord3>,
+    alphas: Vec<f64>,
+}
+
+/// The result of shape alignment.
+#[derive(Clone, Debug)]
+pub struct ShapeAlignment {
+    /// The transformation bringing the candidate onto the reference
+    pub transform: RigidTransform,
+
+    /// The overlap volume after alignment
+    pub overlap: f64,
+
+    /// The shape Tanimoto score after alignment
+    pub tanimoto: f64,
+}
+
+impl GaussianShape {
+    /// Construct from `positions` with the radius of each point, such as
+    /// the van der Waals radius of each atom.
+    pub fn new(positions: &[Coord3], radii: &[f64]) -> Result<Self> {
+        if positions.is_empty() {
+            bail!("no point for shape!");
+        }
+        if positions.len() != radii.len() {
+            bail!("radii size mismatch!");
+        }
+        if radii.iter().any(|&r| !r.is_finite() || r <= 0.0) {
+            bail!("invalid radii: {:?}", radii);
+        }
+
+        let alphas = radii
+            .iter()
+            .map(|&r| PI * (3.0 * GAUSSIAN_HEIGHT / (4.0 * PI * r.powi(3))).powf(2.0 / 3.0))
+            .collect();
+        Ok(Self {
+            centers: positions.to_vec(),
+            alphas,
+        })
+    }
+
+    /// Return the centers of Gaussians.
+    pub fn positions(&self) -> &[Coord3] {
+        &self.centers
+    }
+
+    /// Return the shape transformed by `t`.
+    pub fn transformed(&self, t: &RigidTransform) -> Self {
+        Self {
+            centers: t.apply(&self.centers),
+            alphas: self.alphas.clone(),
+        }
+    }
+
+    /// Return the overlap volume with itself.
+    pub fn self_overlap(&self) -> f64 {
+        self.overlap(self)
+    }
+
+    /// Return the overlap volume with `other` shape.
+    pub fn overlap(&self, other: &Self) -> f64 {
+        self.pair_overlaps(other).map(|(_, _, v)| v).sum()
+    }
+
+    /// Return the shape Tanimoto score with `other` shape, ranging from 0
+    /// (no overlap) to 1 (identical shapes).
+    pub fn tanimoto(&self, other: &Self) -> f64 {
+        let vab = self.overlap(other);
+        vab / (self.self_overlap() + other.self_overlap() - vab)
+    }
+
+    /// Return the overlap volume with `other` shape, and its gradient with
+    /// respect to rigid-body parameters of `other`: the infinitesimal
+    /// rotation vector about the center of geometry of `other`, followed by
+    /// the translation vector.
+    pub fn overlap_gradient(&self, other: &Self) -> (f64, [f64; 6]) {
+        let center = Vector3f::from(other.centers.center_of_geometry());
+        let mut overlap = 0.0;
+        let mut torque = Vector3f::zeros();
+        let mut force = Vector3f::zeros();
+        for (i, j, v) in self.pair_overlaps(other) {
+            let (ai, aj) = (self.alphas[i], other.alphas[j]);
+            let k = ai * aj / (ai + aj);
+            let d = Vector3f::from(other.centers[j]) - Vector3f::from(self.centers[i]);
+            // dV/dx_j
+            let g = -2.0 * k * v * d;
+            overlap += v;
+            force += g;
+            torque += (Vector3f::from(other.centers[j]) - center).cross(&g);
+        }
+        (overlap, [torque[0], torque[1], torque[2], force[0], force[1], force[2]])
+    }
+
+    /// Iterate over overlap volumes of all Gaussian pairs.
+    fn pair_overlaps<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = (usize, usize, f64)> + 'a {
+        let p2 = GAUSSIAN_HEIGHT * GAUSSIAN_HEIGHT;
+        (0..self.centers.len()).flat_map(move |i| {
+            (0..other.centers.len()).map(move |j| {
+                let (ai, aj) = (self.alphas[i], other.alphas[j]);
+                let d2 = self.centers[i].vecdist_squared(&other.centers[j]);
+                let v = p2 * (PI / (ai + aj)).powf(1.5) * (-ai * aj / (ai + aj) * d2).exp();
+                (i, j, v)
+            })
+        })
+    }
+
+    /// Align `candidate` shape onto `self` by maximizing the overlap volume.
+    /// The optimization starts from orientations matching the principal
+    /// axes of both shapes, and the best result is kept.
+    pub fn align(&self, candidate: &Self) -> ShapeAlignment {
+        let ones = vec![1.0; self.centers.len()];
+        let center_ref = Vector3f::from(self.centers.center_of_geometry());
+        let axes_ref = axes_matrix(self.centers.principal_axes(&ones));
+        let ones = vec![1.0; candidate.centers.len()];
+        let center_can = Vector3f::from(candidate.centers.center_of_geometry());
+        let axes_can = axes_matrix(candidate.centers.principal_axes(&ones));
+
+        let mut best: Option<ShapeAlignment> = None;
+        for &(sx, sy, sz) in &[(1.0, 1.0, 1.0), (1.0, -1.0, -1.0), (-1.0, 1.0, -1.0), (-1.0, -1.0, 1.0)] {
+            let flip = Matrix3f::from_diagonal(&Vector3f::new(sx, sy, sz));
+            let rotation = axes_ref.transpose() * flip * axes_can;
+            let seed = RigidTransform::new(rotation, center_ref - rotation * center_can);
+            let transform = self.maximize_overlap(candidate, seed);
+            let moved = candidate.transformed(&transform);
+            let overlap = self.overlap(&moved);
+            if best.as_ref().is_none_or(|b| overlap > b.overlap) {
+                best = Some(ShapeAlignment {
+                    transform,
+                    overlap,
+                    tanimoto: self.tanimoto(&moved),
+                });
+            }
+        }
+        best.expect("shape alignment")
+    }
+
+    /// Maximize the overlap with `candidate` by gradient ascent over its
+    /// rigid-body parameters, starting from `transform`.
+    fn maximize_overlap(&self, candidate: &Self, mut transform: RigidTransform) -> RigidTransform {
+        let mut moved = candidate.transformed(&transform);
+        let (mut overlap, mut gradient) = self.overlap_gradient(&moved);
+        let mut step = 1e-3 / (gradient.iter().map(|x| x * x).sum::<f64>().sqrt() + 1e-12);
+        for _ in 0..1000 {
+            let center = Vector3f::from(moved.centers.center_of_geometry());
+            let omega = Vector3f::new(gradient[0], gradient[1], gradient[2]) * step;
+            let shift = center + Vector3f::new(gradient[3], gradient[4], gradient[5]) * step;
+            let angle = omega.norm();
+            let rotation = if angle > 0.0 {
+                RigidTransform::from_axis_angle(omega.into(), angle)
+            } else {
+                RigidTransform::identity()
+            };
+            let delta = RigidTransform::from_translation(shift.into()) * rotation * RigidTransform::from_translation((-center).into());
+
+            let trial = candidate.transformed(&(delta * transform));
+            let (trial_overlap, trial_gradient) = self.overlap_gradient(&trial);
+            if trial_overlap > overlap {
+                let gain = trial_overlap - overlap;
+                transform = delta * transform;
+                moved = trial;
+                overlap = trial_overlap;
+                gradient = trial_gradient;
+                step *= 1.5;
+                if gain < 1e-10 * overlap {
+                    break;
+                }
+            } else {
+                step *= 0.5;
+                if step < 1e-14 {
+                    break;
+                }
+            }
+        }
+        transform
+    }
+}
+
+/// Return the matrix with `axes` as rows.
+fn axes_matrix(axes: [Coord3; 3]) -> Matrix3f {
+    Matrix3f::from_rows(&axes.map(|a| Vector3f::from(a).transpose()))
+}
+// 9e2d6b48 ends here
+
+// [[file:../gchemol-geometry.note::27b0f4d5][27b0f4d5]]
+#[test]
+fn test_gaussian_shape() {
+    use approx::assert_relative_eq;
+
+    // a distorted ethanol-like shape
+    let positions = [
+        [-1.17, -0.40, 0.0],
+        [0.17, 0.35, 0.10],
+        [1.23, -0.56, -0.20],
+        [-1.90, 0.40, 0.30],
+        [2.10, -0.10, 0.05],
+    ];
+    let radii = [1.7, 1.7, 1.52, 1.2, 1.2];
+    let shape = GaussianShape::new(&positions, &radii).unwrap();
+    assert_relative_eq!(shape.tanimoto(&shape), 1.0, epsilon = 1e-12);
+    // the volume of a single Gaussian reproduces the hard-sphere volume
+    let atom = GaussianShape::new(&[[0.0; 3]], &[1.7]).unwrap();
+    assert_relative_eq!(atom.self_overlap(), 4.0 / 3.0 * PI * 1.7f64.powi(3), epsilon = 0.5);
+
+    // compare gradient with finite differences
+    let t = RigidTransform::from_axis_angle([0.2, 1.0, -0.4], 0.3) * RigidTransform::from_translation([0.3, -0.2, 0.5]);
+    let moved = shape.transformed(&t);
+    let (overlap, gradient) = shape.overlap_gradient(&moved);
+    assert_relative_eq!(overlap, shape.overlap(&moved), epsilon = 1e-10);
+    let center = Vector3f::from(moved.positions().center_of_geometry());
+    let h = 1e-6;
+    for k in 0..6 {
+        let displace = |sign: f64| {
+            let mut d = [0.0; 3];
+            d[k % 3] = sign * h;
+            let t = if k < 3 {
+                RigidTransform::from_translation(center.into())
+                    * RigidTransform::from_axis_angle(d, h)
+                    * RigidTransform::from_translation((-center).into())
+            } else {
+                RigidTransform::from_translation(d)
+            };
+            shape.overlap(&moved.transformed(&t))
+        };
+        assert_relative_eq!(gradient[k], (displace(1.0) - displace(-1.0)) / (2.0 * h), epsilon = 1e-5);
+    }
+
+    // recover the shape after a large rotation
+    let t = RigidTransform::from_axis_angle([-0.5, 0.2, 1.0], 2.6) * RigidTransform::from_translation([-1.5, 0.7, 2.4]);
+    let candidate = shape.transformed(&t);
+    assert!(shape.tanimoto(&candidate) < 0.1);
+    let aligned = shape.align(&candidate);
+    assert_relative_eq!(aligned.tanimoto, 1.0, epsilon = 1e-4);
+    assert_relative_eq!(aligned.overlap, shape.self_overlap(), epsilon = 1e-3);
+
+    assert!(GaussianShape::new(&[], &[]).is_err());
+}
+// 27b0f4d5 ends here
@@ -0,0 +1,404 @@
+// [[file:../gchemol-geometry.note::b1e6d8a4][b1e6d8a4]]
+//! Similarity metrics between two structures of corresponding points
+
+use super::*;
+use crate::transform::get_distance_matrix;
+use vecfx::*;
+// b1e6d8a4 ends here
+
+// [[file:../gchemol-geometry.note::6f2a9c51][6f2a9c51]]
+/// The switching function for counting contacts smoothly.
+#[derive(Clone, Copy, Debug)]
+pub enum SwitchingFunction {
+    /// 1 for distance no more than `r0`, otherwise 0
+    Step { r0: f64 },
+    /// (1 - (r/r0)^n) / (1 - (r/r0)^m), commonly with n = 6 and m = 12
+    Rational { r0: f64, n: i32, m: i32 },
+    /// 1 / (1 + exp(beta (r - lambda r_native))) relative to the native
+    /// distance, commonly with beta = 5.0 / Å and lambda = 1.8
+    ///
+    /// Best, R. B.; Hummer, G.; Eaton, W. A. PNAS 2013, 110, 17874–17879.
+    Sigmoid { beta: f64, lambda: f64 },
+}
+
+impl SwitchingFunction {
+    /// Return the contact value of two points at distance `r`, with the
+    /// distance `r_native` in the native structure.
+    pub fn eval(&self, r: f64, r_native: f64) -> f64 {
+        match *self {
+            Self::Step { r0 } => {
+                if r <= r0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Rational { r0, n, m } => {
+                let x = r / r0;
+                let den = 1.0 - x.powi(m);
+                // the limit at r = r0
+                if den.abs() < 1e-8 {
+                    n as f64 / m as f64
+                } else {
+                    (1.0 - x.powi(n)) / den
+                }
+            }
+            Self::Sigmoid { beta, lambda } => 1.0 / (1.0 + (beta * (r - lambda * r_native)).exp()),
+        }
+    }
+}
+
+/// Check two structures for comparison.
+fn check_sizes(positions: &[Coord3], reference: &[Coord3]) -> Result<()> {
+    if positions.len() != reference.len() {
+        bail!("points size mismatch!");
+    }
+    if positions.len() < 2 {
+        bail!("too few points for distance comparison!");
+    }
+    Ok(())
+}
+
+/// Return the distance RMSD between two structures, i.e. the RMS deviation
+/// of all pairwise distances. The result is invariant to rigid motion and
+/// reflection, and needs no superposition.
+///
+/// Parameters
+/// ----------
+/// * positions: coordinates of the structure to be compared
+/// * reference: coordinates of the reference structure
+/// * cutoff   : if given, only pairs within the cutoff distance in the
+///   reference structure are included
+pub fn drmsd(positions: &[Coord3], reference: &[Coord3], cutoff: Option<f64>) -> Result<f64> {
+    check_sizes(positions, reference)?;
+
+    let dm = get_distance_matrix(positions);
+    let dm_ref = get_distance_matrix(reference);
+    let npts = positions.len();
+    let mut sum = 0.0;
+    let mut npairs = 0;
+    for i in 0..npts {
+        for j in (i + 1)..npts {
+            if cutoff.is_none_or(|rc| dm_ref[i][j] <= rc) {
+                sum += (dm[i][j] - dm_ref[i][j]).powi(2);
+                npairs += 1;
+            }
+        }
+    }
+    if npairs == 0 {
+        bail!("no pair within cutoff {:?}!", cutoff);
+    }
+
+    Ok((sum / npairs as f64).sqrt())
+}
+
+/// Return the fraction of native contacts Q. Native contacts are pairs of
+/// points within `contact_cutoff` in the reference (native) structure, and
+/// each contact is counted in `positions` using the `switching` function.
+pub fn fraction_native_contacts(
+    positions: &[Coord3],
+    reference: &[Coord3],
+    contact_cutoff: f64,
+    switching: SwitchingFunction,
+) -> Result<f64> {
+    check_sizes(positions, reference)?;
+
+    let dm = get_distance_matrix(positions);
+    let dm_ref = get_distance_matrix(reference);
+    let npts = positions.len();
+    let mut q = 0.0;
+    let mut ncontacts = 0;
+    for i in 0..npts {
+        for j in (i + 1)..npts {
+            if dm_ref[i][j] <= contact_cutoff {
+                q += switching.eval(dm[i][j], dm_ref[i][j]);
+                ncontacts += 1;
+            }
+        }
+    }
+    if ncontacts == 0 {
+        bail!("no native contact within cutoff {}!", contact_cutoff);
+    }
+
+    Ok(q / ncontacts as f64)
+}
+// 6f2a9c51 ends here
+
+// [[file:../gchemol-geometry.note::3c9f4e17][3c9f4e17]]
+/// The minimum number of points for superposition in score search.
+const MIN_FIT_POINTS: usize = 3;
+
+/// The TM-score of a structure with the reference.
+#[derive(Clone, Debug)]
+pub struct TmScore {
+    /// The TM-score normalized by the number of reference points
+    pub score: f64,
+
+    /// The distance scale used in scoring
+    pub d0: f64,
+
+    /// The superposition achieving the score
+    pub superposition: Superposition,
+}
+
+/// The global distance test (GDT) score of a structure with the reference.
+#[derive(Clone, Debug)]
+pub struct GdtScore {
+    /// The average of `fractions`
+    pub score: f64,
+
+    /// The maximum fraction of points within each distance cutoff
+    pub fractions: Vec<f64>,
+
+    /// The superposition achieving the fraction at each cutoff
+    pub superpositions: Vec<Superposition>,
+}
+
+/// Superpose `positions` onto `reference` using only points in `subset`,
+/// and return the superposition with deviations of all points, or None if
+/// the subset cannot be superposed, such as for collinear points.
+fn fit_subset(positions: &[Coord3], reference: &[Coord3], subset: &[usize]) -> Option<(Superposition, Vec<f64>)> {
+    let sp = Superpose::new(positions).with_selection(subset, subset).try_onto(reference, None).ok()?;
+    let deviations = sp.try_apply(positions).ok()?.iter().zip(reference).map(|(p, r)| p.vecdist(r)).collect();
+    Some((sp, deviations))
+}
+
+/// Search for the superposition maximizing `score` of point deviations.
+/// Superpositions on contiguous fragments of various lengths are refined by
+/// iteratively fitting the points within `cutoff` distance. Fragments which
+/// cannot be superposed are skipped, and an error is returned if none can.
+fn search_superposition(
+    positions: &[Coord3],
+    reference: &[Coord3],
+    cutoff: f64,
+    score: impl Fn(&[f64]) -> f64,
+) -> Result<(f64, Superposition)> {
+    let npts = positions.len();
+    let mut best: Option<(f64, Superposition)> = None;
+    let mut len = npts;
+    while len >= MIN_FIT_POINTS {
+        let step = (len / 2).max(1);
+        for start in (0..=npts - len).step_by(step) {
+            let mut subset: Vec<usize> = (start..start + len).collect();
+            for _ in 0..20 {
+                let Some((sp, deviations)) = fit_subset(positions, reference, &subset) else {
+                    break;
+                };
+                let s = score(&deviations);
+                if best.as_ref().is_none_or(|(b, _)| s > *b) {
+                    best = Some((s, sp));
+                }
+
+                let new_subset: Vec<usize> = (0..npts).filter(|&i| deviations[i] <= cutoff).collect();
+                if new_subset.len() < MIN_FIT_POINTS || new_subset == subset {
+                    break;
+                }
+                subset = new_subset;
+            }
+        }
+        len /= 2;
+    }
+    match best {
+        Some(best) => Ok(best),
+        None => bail!("no fragment can be superposed!"),
+    }
+}
+
+/// Return the TM-score of `positions` with `reference` of corresponding
+/// points, searching for the superposition that maximizes the score.
+///
+/// # Reference
+///
+/// Zhang, Y.; Skolnick, J. Proteins 2004, 57, 702–710.
+pub fn tm_score(positions: &[Coord3], reference: &[Coord3]) -> Result<TmScore> {
+    if positions.len() != reference.len() {
+        bail!("points size mismatch!");
+    }
+    let npts = reference.len();
+    if npts < MIN_FIT_POINTS {
+        bail!("too few points for TM-score: {}", npts);
+    }
+
+    let d0 = if npts > 21 {
+        1.24 * (npts as f64 - 15.0).cbrt() - 1.8
+    } else {
+        0.5
+    };
+    let d_search = d0.clamp(4.5, 8.0);
+    let score = |deviations: &[f64]| deviations.iter().map(|d| 1.0 / (1.0 + (d / d0).powi(2))).sum::<f64>() / npts as f64;
+    let (score, superposition) = search_superposition(positions, reference, d_search, score)?;
+
+    Ok(TmScore { score, d0, superposition })
+}
+
+/// Return the GDT score as the average of maximum fractions of points
+/// within each distance of `cutoffs` after superposition.
+pub fn gdt(positions: &[Coord3], reference: &[Coord3], cutoffs: &[f64]) -> Result<GdtScore> {
+    if positions.len() != reference.len() {
+        bail!("points size mismatch!");
+    }
+    let npts = reference.len();
+    if npts < MIN_FIT_POINTS {
+        bail!("too few points for GDT: {}", npts);
+    }
+    if cutoffs.is_empty() {
+        bail!("no distance cutoff for GDT!");
+    }
+
+    let (fractions, superpositions): (Vec<_>, Vec<_>) = cutoffs
+        .iter()
+        .map(|&c| {
+            let score = |deviations: &[f64]| deviations.iter().filter(|&&d| d <= c).count() as f64 / npts as f64;
+            search_superposition(positions, reference, c, score)
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    let score = fractions.iter().sum::<f64>() / fractions.len() as f64;
+
+    Ok(GdtScore {
+        score,
+        fractions,
+        superpositions,
+    })
+}
+
+/// Return the GDT-TS score using cutoffs of 1, 2, 4 and 8 Å.
+pub fn gdt_ts(positions: &[Coord3], reference: &[Coord3]) -> Result<GdtScore> {
+    gdt(positions, reference, &[1.0, 2.0, 4.0, 8.0])
+}
+
+/// Return the GDT-HA (high accuracy) score using cutoffs of 0.5, 1, 2 and 4 Å.
+pub fn gdt_ha(positions: &[Coord3], reference: &[Coord3]) -> Result<GdtScore> {
+    gdt(positions, reference, &[0.5, 1.0, 2.0, 4.0])
+}
+
+/// Return the local distance difference test (LDDT) score, which needs no
+/// superposition. Pairs of points within `inclusion_radius` (commonly 15 Å)
+/// in the reference are checked for preserved distances within 0.5, 1, 2
+/// and 4 Å, and the preserved fractions are averaged.
+///
+/// # Reference
+///
+/// Mariani, V.; Biasini, M.; Barbato, A.; Schwede, T. Bioinformatics 2013, 29, 2722–2728.
+pub fn lddt(positions: &[Coord3], reference: &[Coord3], inclusion_radius: f64) -> Result<f64> {
+    check_sizes(positions, reference)?;
+
+    let dm = get_distance_matrix(positions);
+    let dm_ref = get_distance_matrix(reference);
+    let npts = positions.len();
+    let thresholds = [0.5, 1.0, 2.0, 4.0];
+    let mut preserved = 0;
+    let mut npairs = 0;
+    for i in 0..npts {
+        for j in (i + 1)..npts {
+            if dm_ref[i][j] <= inclusion_radius {
+                let diff = (dm[i][j] - dm_ref[i][j]).abs();
+                preserved += thresholds.iter().filter(|&&t| diff < t).count();
+                npairs += 1;
+            }
+        }
+    }
+    if npairs == 0 {
+        bail!("no pair within inclusion radius {}!", inclusion_radius);
+    }
+
+    Ok(preserved as f64 / (npairs * thresholds.len()) as f64)
+}
+// 3c9f4e17 ends here
+
+// [[file:../gchemol-geometry.note::0d7e3b86][0d7e3b86]]
+#[test]
+fn test_drmsd_native_contacts() {
+    use crate::prelude::*;
+    use vecfx::approx::assert_relative_eq;
+
+    // a zigzag chain with neighbors about 3.8 Å apart
+    let reference: Vec<Coord3> = (0..7)
+        .map(|i| [3.3 * i as f64, 1.9 * (i % 2) as f64, 0.8 * (i % 3) as f64])
+        .collect();
+
+    // invariant to rigid motion and reflection
+    let t = RigidTransform::from_axis_angle([0.2, 0.9, 0.5], -2.3) * RigidTransform::from_translation([0.5, 1.5, -2.5]);
+    let mut moved = t.apply(&reference);
+    moved.mirror_invert();
+    assert_relative_eq!(drmsd(&moved, &reference, None).unwrap(), 0.0, epsilon = 1e-10);
+    let step = SwitchingFunction::Step { r0: 4.5 };
+    assert_relative_eq!(fraction_native_contacts(&moved, &reference, 4.5, step).unwrap(), 1.0);
+
+    // stretch the last point away
+    let mut distorted = reference.clone();
+    distorted[6][0] += 3.0;
+    let d_all = drmsd(&distorted, &reference, None).unwrap();
+    let d_local = drmsd(&distorted, &reference, Some(4.5)).unwrap();
+    assert!(d_all > 0.5 && d_local > 0.0);
+    assert!(drmsd(&distorted, &reference, Some(0.1)).is_err());
+
+    let q = fraction_native_contacts(&distorted, &reference, 4.5, step).unwrap();
+    assert!(q < 1.0);
+    let rational = SwitchingFunction::Rational { r0: 4.5, n: 6, m: 12 };
+    assert_relative_eq!(rational.eval(4.5, 4.5), 0.5, epsilon = 1e-12);
+    assert_relative_eq!(rational.eval(4.5 + 1e-6, 4.5), 0.5, epsilon = 1e-5);
+    let sigmoid = SwitchingFunction::Sigmoid { beta: 5.0, lambda: 1.8 };
+    let q_native = fraction_native_contacts(&reference, &reference, 4.5, sigmoid).unwrap();
+    let q = fraction_native_contacts(&distorted, &reference, 4.5, sigmoid).unwrap();
+    assert!(q_native > 0.99 && q < q_native);
+}
+// 0d7e3b86 ends here
+
+// [[file:../gchemol-geometry.note::e7a05b2c][e7a05b2c]]
+#[test]
+fn test_structure_scores() {
+    use vecfx::approx::assert_relative_eq;
+
+    // a helix-like chain
+    let reference: Vec<Coord3> = (0..30)
+        .map(|i| {
+            let t = (100.0 * i as f64).to_radians();
+            [2.3 * t.cos(), 2.3 * t.sin(), 1.5 * i as f64]
+        })
+        .collect();
+    let t = RigidTransform::from_axis_angle([-1.0, 0.6, 0.3], 1.1) * RigidTransform::from_translation([4.0, -3.0, 0.5]);
+    let moved = t.apply(&reference);
+    assert_relative_eq!(tm_score(&moved, &reference).unwrap().score, 1.0, epsilon = 1e-6);
+    assert_relative_eq!(gdt_ts(&moved, &reference).unwrap().score, 1.0);
+    assert_relative_eq!(lddt(&moved, &reference, 15.0).unwrap(), 1.0);
+
+    // a flexible terminus far away
+    let mut flexible = moved.clone();
+    for p in flexible[24..].iter_mut() {
+        p[0] += 10.0;
+    }
+    let sp = Superpose::new(&flexible).onto(&reference, None);
+    assert!(sp.rmsd > 3.0);
+
+    let tm = tm_score(&flexible, &reference).unwrap();
+    assert!(tm.score > 0.79 && tm.score < 0.85, "{}", tm.score);
+    let superimposed = tm.superposition.apply(&flexible);
+    assert!(superimposed[0].vecdist(&reference[0]) < 1e-6);
+
+    let gdt = gdt_ts(&flexible, &reference).unwrap();
+    assert_eq!(gdt.fractions.len(), 4);
+    assert_relative_eq!(gdt.fractions[0], 0.8, epsilon = 1e-8);
+    assert!(gdt.score >= 0.8);
+    let gdt_ha = gdt_ha(&flexible, &reference).unwrap();
+    assert!(gdt_ha.score <= gdt.score);
+
+    let score = lddt(&flexible, &reference, 15.0).unwrap();
+    assert!(score > 0.5 && score < 1.0);
+    assert!(tm_score(&flexible[1..], &reference).is_err());
+
+    // fragments which cannot be superposed are skipped, and an error is
+    // returned if no fragment can be superposed
+    let mut kinked = reference.clone();
+    for (i, p) in kinked[..8].iter_mut().enumerate() {
+        *p = [2.3, 0.0, 1.5 * i as f64 - 12.0];
+    }
+    let moved = t.apply(&kinked);
+    assert_relative_eq!(tm_score(&moved, &kinked).unwrap().score, 1.0, epsilon = 1e-6);
+    assert_relative_eq!(gdt_ts(&moved, &kinked).unwrap().score, 1.0);
+    let coincident = vec![[1.0, 2.0, 3.0]; 10];
+    assert!(tm_score(&coincident, &coincident).is_err());
+    assert!(gdt_ts(&coincident, &coincident).is_err());
+}
+// e7a05b2c ends here
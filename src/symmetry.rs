@@ -0,0 +1,782 @@
+// [[file:../gchemol-geometry.note::8c41d2e7][8c41d2e7]]
+//! Point-group symmetry of point sets
+
+use super::*;
+use crate::prelude::*;
+use vecfx::*;
+
+use vecfx::nalgebra as na;
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+// 8c41d2e7 ends here
+
+// [[file:../gchemol-geometry.note::d0f73b95][d0f73b95]]
+/// The maximum order of rotation axes to be detected.
+const MAX_ORDER: usize = 8;
+
+/// The order of the largest point group (Ih) with finite axes.
+const MAX_GROUP_ORDER: usize = 120;
+
+/// A symmetry element of a point group, with axes and normals normalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymmetryElement {
+    /// The identity operation
+    Identity,
+    /// Inversion through the center
+    Inversion,
+    /// Proper rotation about `axis` by `angle` in radian
+    Rotation { axis: Coord3, angle: f64 },
+    /// Reflection through the plane with `normal`
+    Reflection { normal: Coord3 },
+    /// Rotation about `axis` by `angle` followed by reflection through the
+    /// plane perpendicular to `axis`
+    ImproperRotation { axis: Coord3, angle: f64 },
+}
+
+/// A symmetry operation of a point set.
+#[derive(Clone, Debug)]
+pub struct SymmetryOperation {
+    /// The kind of the operation
+    pub element: SymmetryElement,
+
+    /// The transformation in Cartesian coordinates, keeping the center of the
+    /// point set fixed
+    pub transform: RigidTransform,
+
+    /// The operation moves point `i` to the position of point `permutation[i]`
+    pub permutation: Vec<usize>,
+}
+
+/// The detected point group of a point set.
+#[derive(Clone, Debug)]
+pub struct PointGroup {
+    /// The Schoenflies symbol, such as "C2v", "D6h", "Td" or "D∞h"
+    pub name: String,
+
+    /// The center of the point set kept fixed by all operations
+    pub center: Coord3,
+
+    /// All symmetry operations, with the identity first. For linear point
+    /// sets only the finite subgroup C2v or D2h is included.
+    pub operations: Vec<SymmetryOperation>,
+
+    /// Classes of symmetry-equivalent points
+    pub classes: Vec<Vec<usize>>,
+}
+
+impl PointGroup {
+    /// Return the number of symmetry operations.
+    pub fn order(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Return the rotational symmetry number, i.e. the number of proper
+    /// rotations including the identity.
+    pub fn symmetry_number(&self) -> usize {
+        match self.name.as_str() {
+            "Kh" | "C∞v" => 1,
+            "D∞h" => 2,
+            _ => self
+                .operations
+                .iter()
+                .filter(|op| matches!(op.element, SymmetryElement::Identity | SymmetryElement::Rotation { .. }))
+                .count(),
+        }
+    }
+
+    /// Make `positions` exactly symmetric by averaging over all symmetry
+    /// operations, which should be detected from the same positions.
+    pub fn symmetrize(&self, positions: &[Coord3]) -> Result<Symmetrized> {
+        let npts = positions.len();
+        if self.operations.iter().any(|op| op.permutation.len() != npts) {
+            bail!("points size mismatch!");
+        }
+
+        // average positions of points which each operation moves onto point i
+        let mut sums = vec![Vector3f::zeros(); npts];
+        for op in &self.operations {
+            let inverse = op.transform.inverse();
+            for (i, &j) in op.permutation.iter().enumerate() {
+                sums[i] += Vector3f::from(inverse.apply_point(positions[j]));
+            }
+        }
+        let n = self.order() as f64;
+        let symmetrized: Vec<Coord3> = sums.into_iter().map(|p| (p / n).into()).collect();
+
+        let max_displacement = symmetrized
+            .iter()
+            .zip(positions)
+            .map(|(p, q)| p.vecdist(q))
+            .fold(0.0, f64::max);
+        Ok(Symmetrized {
+            positions: symmetrized,
+            max_displacement,
+            point_group: self.name.clone(),
+        })
+    }
+
+    /// Return all subgroups with Schoenflies symbol `name`, such as the four
+    /// C3v subgroups of Td, each keeping a different point fixed. The group
+    /// itself is included if it is named `name`. For linear point sets only
+    /// subgroups of the finite operations are found.
+    pub fn subgroups(&self, name: &str) -> Vec<PointGroup> {
+        let n = self.order();
+        // the multiplication table, with operations matched by their
+        // permutations and matrices
+        let table: Vec<Vec<usize>> = self
+            .operations
+            .iter()
+            .map(|a| {
+                self.operations
+                    .iter()
+                    .map(|b| {
+                        let rot = a.transform.rotation * b.transform.rotation;
+                        let perm: Vec<usize> = b.permutation.iter().map(|&i| a.permutation[i]).collect();
+                        self.operations
+                            .iter()
+                            .position(|op| op.permutation == perm && (op.transform.rotation - rot).norm() < 1e-6)
+                            .expect("closed group")
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut found = vec![];
+        for members in all_subgroups(&table) {
+            let operations: Vec<_> = members.iter().map(|&k| self.operations[k].clone()).collect();
+            let subgroup_name = if members.len() == n {
+                self.name.clone()
+            } else {
+                let elements: Vec<_> = operations.iter().map(|op| op.element).collect();
+                point_group_name(&elements, false)
+            };
+            if subgroup_name == name {
+                let ops: Vec<_> = operations.iter().map(|op| (op.transform.rotation, op.permutation.clone())).collect();
+                found.push(PointGroup {
+                    name: subgroup_name,
+                    center: self.center,
+                    classes: equivalent_classes(ops[0].1.len(), &ops),
+                    operations,
+                });
+            }
+        }
+        found
+    }
+}
+
+/// Positions made exactly symmetric.
+#[derive(Clone, Debug)]
+pub struct Symmetrized {
+    /// The symmetrized positions
+    pub positions: Vec<Coord3>,
+
+    /// The maximum displacement of points from the input positions
+    pub max_displacement: f64,
+
+    /// The name of the point group used for symmetrization
+    pub point_group: String,
+}
+
+/// Project a nearly symmetric structure onto the exact symmetric structure
+/// of point group `point_group`, such as "C2v" or "Td". Symmetry operations
+/// are detected within distance tolerance `tol`, and an error is returned if
+/// `point_group` is neither the detected point group nor one of its
+/// subgroups. Among subgroups of the same name, the one requiring the
+/// smallest displacement is used.
+pub fn symmetrize(positions: &[Coord3], labels: &[usize], point_group: &str, tol: f64) -> Result<Symmetrized> {
+    let pg = detect_point_group(positions, labels, tol)?;
+    let mut best: Option<Symmetrized> = None;
+    for subgroup in pg.subgroups(point_group) {
+        let symmetrized = subgroup.symmetrize(positions)?;
+        if best.as_ref().is_none_or(|b| symmetrized.max_displacement < b.max_displacement) {
+            best = Some(symmetrized);
+        }
+    }
+    match best {
+        Some(best) => Ok(best),
+        None => bail!("{} is not a subgroup of point group {} found within tolerance {}", point_group, pg.name, tol),
+    }
+}
+
+/// Detect the point group of `positions` within distance tolerance `tol`.
+/// Only points having the same label can be symmetry-equivalent.
+///
+/// Rotation axes up to order 8 are detected.
+pub fn detect_point_group(positions: &[Coord3], labels: &[usize], tol: f64) -> Result<PointGroup> {
+    let npts = positions.len();
+    if npts == 0 {
+        bail!("no point for symmetry detection!");
+    }
+    if labels.len() != npts {
+        bail!("labels size mismatch!");
+    }
+
+    let center = positions.center_of_geometry();
+    let centered: Vec<Vector3f> = positions.iter().map(|&p| Vector3f::from(p) - Vector3f::from(center)).collect();
+    let classes = equivalent_candidates(&centered, labels, tol);
+
+    // linear point sets have infinite rotations about the molecular axis,
+    // which is the principal axis with the smallest moment
+    let axes = positions.principal_axes(&vec![1.0; npts]).map(Vector3f::from);
+    let axis = axes[0];
+    let spherical = centered.iter().all(|p| p.norm() < tol);
+    let linear = centered.iter().all(|p| (p - p.dot(&axis) * axis).norm() < tol);
+    let (directions, max_order) = if linear {
+        let u = axis.cross(&Vector3f::x());
+        let u = if u.norm() > 0.1 { u.normalize() } else { axis.cross(&Vector3f::y()).normalize() };
+        (vec![axis, u, axis.cross(&u)], 2)
+    } else {
+        (candidate_directions(&centered, &classes, &axes, tol), MAX_ORDER)
+    };
+
+    // collect operations found on candidate symmetry elements
+    let mut ops = Operations::default();
+    ops.insert(Matrix3f::identity(), (0..npts).collect());
+    let mut try_add = |rot: Matrix3f| match_operation(&centered, &classes, &rot, tol).map(|perm| ops.insert(rot, perm));
+    try_add(-Matrix3f::identity());
+    for axis in directions {
+        let reflection = Matrix3f::identity() - 2.0 * axis * axis.transpose();
+        try_add(reflection);
+        let axis = na::Unit::new_normalize(axis);
+        for order in 2..=max_order {
+            let rot = na::Rotation3::from_axis_angle(&axis, 2.0 * PI / order as f64).into_inner();
+            if try_add(rot).is_some() {
+                let rot = na::Rotation3::from_axis_angle(&axis, PI / order as f64).into_inner();
+                try_add(reflection * rot);
+            }
+        }
+    }
+
+    ops.close()?;
+    ops.idealize();
+    let ops = ops.items;
+
+    let elements: Vec<_> = ops.iter().map(|(rot, _)| classify_operation(rot)).collect();
+    let name = if spherical {
+        "Kh".to_string()
+    } else {
+        point_group_name(&elements, linear)
+    };
+    let classes = equivalent_classes(npts, &ops);
+    let operations = ops
+        .into_iter()
+        .zip(elements)
+        .map(|((rot, permutation), element)| {
+            let translation = Vector3f::from(center) - rot * Vector3f::from(center);
+            SymmetryOperation {
+                element,
+                transform: RigidTransform::new(rot, translation),
+                permutation,
+            }
+        })
+        .collect();
+
+    Ok(PointGroup {
+        name,
+        center,
+        operations,
+        classes,
+    })
+}
+// d0f73b95 ends here
+
+// [[file:../gchemol-geometry.note::1f5b8a3c][1f5b8a3c]]
+/// Assign candidate classes of symmetry-equivalent points by their labels
+/// and distances to the center.
+fn equivalent_candidates(centered: &[Vector3f], labels: &[usize], tol: f64) -> Vec<usize> {
+    let npts = centered.len();
+    let mut order: Vec<usize> = (0..npts).collect();
+    order.sort_by(|&i, &j| labels[i].cmp(&labels[j]).then(centered[i].norm().total_cmp(&centered[j].norm())));
+
+    let mut classes = vec![0; npts];
+    for k in 1..npts {
+        let (i, j) = (order[k - 1], order[k]);
+        let same = labels[i] == labels[j] && (centered[j].norm() - centered[i].norm()) < tol;
+        classes[j] = if same { classes[i] } else { classes[i] + 1 };
+    }
+    classes
+}
+
+/// Return unique candidate directions of rotation axes and mirror plane
+/// normals, including principal `axes`.
+fn candidate_directions(centered: &[Vector3f], classes: &[usize], axes: &[Vector3f; 3], tol: f64) -> Vec<Vector3f> {
+    let npts = centered.len();
+    let mut directions: Vec<Vector3f> = axes.to_vec();
+    for i in 0..npts {
+        let p = centered[i];
+        directions.push(p);
+        for j in (i + 1)..npts {
+            let q = centered[j];
+            if classes[i] == classes[j] {
+                directions.push(p + q);
+                directions.push(p - q);
+                let pq = p.norm() * q.norm();
+                if pq > tol * tol {
+                    directions.push(p.cross(&q) / pq);
+                }
+            }
+        }
+    }
+
+    // remove duplicates, ignoring the sign of each direction
+    let mut seen = HashSet::new();
+    let mut unique = vec![];
+    for d in directions {
+        if d.norm() < tol {
+            continue;
+        }
+        let mut d = d.normalize();
+        if d.iamax() != d.iamin() && d[d.iamax()] < 0.0 {
+            d = -d;
+        }
+        let key = d.map(|x| (x * 1000.0).round() as i64);
+        if seen.insert((key[0], key[1], key[2])) {
+            unique.push(d);
+        }
+    }
+    unique
+}
+
+/// Return the permutation of points induced by operation `rot`, or None if
+/// `rot` is not a symmetry operation within `tol`.
+fn match_operation(centered: &[Vector3f], classes: &[usize], rot: &Matrix3f, tol: f64) -> Option<Vec<usize>> {
+    let npts = centered.len();
+    let mut perm = vec![0; npts];
+    let mut used = vec![false; npts];
+    for i in 0..npts {
+        let q = rot * centered[i];
+        let j = (0..npts)
+            .filter(|&j| !used[j] && classes[j] == classes[i])
+            .min_by(|&a, &b| (q - centered[a]).norm().total_cmp(&(q - centered[b]).norm()))?;
+        if (q - centered[j]).norm() > tol {
+            return None;
+        }
+        used[j] = true;
+        perm[i] = j;
+    }
+    Some(perm)
+}
+
+/// Symmetry operations as orthogonal matrices with induced permutations,
+/// indexed by permutations for fast lookup.
+#[derive(Default)]
+struct Operations {
+    items: Vec<(Matrix3f, Vec<usize>)>,
+    index: HashMap<Vec<usize>, Vec<usize>>,
+}
+
+impl Operations {
+    /// Return the index of operation `rot` inducing permutation `perm`.
+    fn find(&self, rot: &Matrix3f, perm: &[usize]) -> Option<usize> {
+        let found = self.index.get(perm)?;
+        found.iter().copied().find(|&k| (self.items[k].0 - rot).norm() < 0.1)
+    }
+
+    /// Add operation `rot` if not found.
+    fn insert(&mut self, rot: Matrix3f, perm: Vec<usize>) {
+        if self.find(&rot, &perm).is_none() {
+            self.index.entry(perm.clone()).or_default().push(self.items.len());
+            self.items.push((rot, perm));
+        }
+    }
+
+    /// Return the product of operations `a` and `b`, which applies `b` first.
+    fn product(&self, a: usize, b: usize) -> (Matrix3f, Vec<usize>) {
+        let (rot_a, perm_a) = &self.items[a];
+        let (rot_b, perm_b) = &self.items[b];
+        (rot_a * rot_b, perm_b.iter().map(|&i| perm_a[i]).collect())
+    }
+
+    /// Add all products of operations to form a group.
+    fn close(&mut self) -> Result<()> {
+        let mut done = 0;
+        while done < self.items.len() {
+            let n = self.items.len();
+            for a in 0..n {
+                for b in 0..n {
+                    if a < done && b < done {
+                        continue;
+                    }
+                    let (rot, perm) = self.product(a, b);
+                    self.insert(rot, perm);
+                    if self.items.len() > MAX_GROUP_ORDER {
+                        bail!("too many symmetry operations: tolerance too large?");
+                    }
+                }
+            }
+            done = n;
+        }
+        Ok(())
+    }
+
+    /// Adjust approximate operation matrices to form an exact group, by
+    /// averaging over the multiplication table.
+    fn idealize(&mut self) {
+        let n = self.items.len();
+        let table: Vec<Vec<usize>> = (0..n)
+            .map(|g| {
+                (0..n)
+                    .map(|h| {
+                        let (rot, perm) = self.product(g, h);
+                        self.find(&rot, &perm).expect("closed group")
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let ops = &mut self.items;
+        for _ in 0..100 {
+            let mut change: f64 = 0.0;
+            let idealized: Vec<Matrix3f> = (0..n)
+                .map(|g| {
+                    let avg = (0..n).fold(Matrix3f::zeros(), |s, h| s + ops[table[g][h]].0 * ops[h].0.transpose()) / n as f64;
+                    let svd = avg.svd(true, true);
+                    svd.u.expect("svd u") * svd.v_t.expect("svd v_t")
+                })
+                .collect();
+            for (op, rot) in ops.iter_mut().zip(idealized) {
+                change = change.max((op.0 - rot).amax());
+                op.0 = rot;
+            }
+            if change < 1e-12 {
+                break;
+            }
+        }
+    }
+}
+
+/// Return the rotation axis of proper rotation `rot`, oriented so that the
+/// rotation is counterclockwise.
+fn rotation_axis(rot: &Matrix3f) -> Vector3f {
+    let se = ((rot + rot.transpose()) / 2.0).symmetric_eigen();
+    let i = (0..3).min_by(|&i, &j| (se.eigenvalues[i] - 1.0).abs().total_cmp(&(se.eigenvalues[j] - 1.0).abs())).unwrap();
+    let axis: Vector3f = se.eigenvectors.column(i).into_owned();
+    let w = Vector3f::new(rot[(2, 1)] - rot[(1, 2)], rot[(0, 2)] - rot[(2, 0)], rot[(1, 0)] - rot[(0, 1)]);
+    if w.dot(&axis) < 0.0 {
+        -axis
+    } else {
+        axis
+    }
+}
+
+/// Return the symmetry element of orthogonal matrix `rot`.
+fn classify_operation(rot: &Matrix3f) -> SymmetryElement {
+    const EPS: f64 = 1e-6;
+
+    let improper = rot.determinant() < 0.0;
+    let proper = if improper { -rot } else { *rot };
+    let angle = ((proper.trace() - 1.0) / 2.0).clamp(-1.0, 1.0).acos();
+    let axis: Coord3 = rotation_axis(&proper).into();
+    match (improper, angle) {
+        (false, a) if a < EPS => SymmetryElement::Identity,
+        (false, a) => SymmetryElement::Rotation { axis, angle: a },
+        (true, a) if a < EPS => SymmetryElement::Inversion,
+        (true, a) if PI - a < EPS => SymmetryElement::Reflection { normal: axis },
+        (true, a) => SymmetryElement::ImproperRotation { axis, angle: PI - a },
+    }
+}
+
+/// Return the Schoenflies symbol of the group formed by `elements`.
+fn point_group_name(elements: &[SymmetryElement], linear: bool) -> String {
+    let has_inversion = elements.contains(&SymmetryElement::Inversion);
+    if linear {
+        let name = if has_inversion { "D∞h" } else { "C∞v" };
+        return name.to_string();
+    }
+
+    let mut normals = vec![];
+    let mut has_improper = false;
+    // rotation axes with their orders
+    let mut axes: Vec<(Vector3f, usize)> = vec![];
+    for element in elements {
+        match *element {
+            SymmetryElement::Reflection { normal } => normals.push(Vector3f::from(normal)),
+            SymmetryElement::ImproperRotation { .. } => has_improper = true,
+            SymmetryElement::Rotation { axis, .. } => {
+                let axis = Vector3f::from(axis);
+                match axes.iter_mut().find(|(a, _)| a.dot(&axis).abs() > 0.99) {
+                    Some((_, order)) => *order += 1,
+                    None => axes.push((axis, 2)),
+                }
+            }
+            _ => {}
+        }
+    }
+    let has_reflection = !normals.is_empty();
+
+    // cubic groups have more than one high-order axis
+    if axes.iter().filter(|(_, order)| *order >= 3).count() >= 2 {
+        let max_order = axes.iter().map(|(_, order)| *order).max().unwrap_or(0);
+        let name = match (max_order, has_inversion, has_reflection) {
+            (5, true, _) => "Ih",
+            (5, false, _) => "I",
+            (4, true, _) => "Oh",
+            (4, false, _) => "O",
+            (_, true, _) => "Th",
+            (_, false, true) => "Td",
+            _ => "T",
+        };
+        return name.to_string();
+    }
+
+    let Some(&(principal, n)) = axes.iter().max_by_key(|(_, order)| *order) else {
+        let name = if has_reflection {
+            "Cs"
+        } else if has_inversion {
+            "Ci"
+        } else {
+            "C1"
+        };
+        return name.to_string();
+    };
+
+    let has_perpendicular_c2 = axes.iter().any(|(a, order)| *order == 2 && a.dot(&principal).abs() < 0.01);
+    let has_horizontal_plane = normals.iter().any(|v| v.dot(&principal).abs() > 0.99);
+    if has_perpendicular_c2 {
+        if has_horizontal_plane {
+            format!("D{}h", n)
+        } else if has_reflection {
+            format!("D{}d", n)
+        } else {
+            format!("D{}", n)
+        }
+    } else if has_horizontal_plane {
+        format!("C{}h", n)
+    } else if has_reflection {
+        format!("C{}v", n)
+    } else if has_improper {
+        format!("S{}", 2 * n)
+    } else {
+        format!("C{}", n)
+    }
+}
+
+/// Return members of all subgroups of the group with multiplication table
+/// `table`, where operation 0 is the identity. Subgroups are found by adding
+/// generators one at a time to smaller subgroups.
+fn all_subgroups(table: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = table.len();
+    // the group generated by `generators`
+    let generate = |generators: &[usize]| {
+        let mut members = vec![false; n];
+        members[0] = true;
+        let mut queue = vec![0];
+        while let Some(a) = queue.pop() {
+            for &g in generators {
+                let b = table[a][g];
+                if !members[b] {
+                    members[b] = true;
+                    queue.push(b);
+                }
+            }
+        }
+        members
+    };
+
+    let mut seen: HashSet<Vec<bool>> = HashSet::new();
+    let mut subgroups: Vec<(Vec<bool>, Vec<usize>)> = vec![(generate(&[]), vec![])];
+    seen.insert(subgroups[0].0.clone());
+    let mut k = 0;
+    while k < subgroups.len() {
+        for g in 0..n {
+            if subgroups[k].0[g] {
+                continue;
+            }
+            let mut generators = subgroups[k].1.clone();
+            generators.push(g);
+            let members = generate(&generators);
+            if seen.insert(members.clone()) {
+                subgroups.push((members, generators));
+            }
+        }
+        k += 1;
+    }
+
+    subgroups
+        .into_iter()
+        .map(|(members, _)| (0..n).filter(|&i| members[i]).collect())
+        .collect()
+}
+
+/// Return classes of points related by any operation in `ops`.
+fn equivalent_classes(npts: usize, ops: &[(Matrix3f, Vec<usize>)]) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..npts).collect();
+    fn root(parent: &[usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            i = parent[i];
+        }
+        i
+    }
+    for (_, perm) in ops {
+        for (i, &j) in perm.iter().enumerate() {
+            let (ri, rj) = (root(&parent, i), root(&parent, j));
+            parent[ri.max(rj)] = ri.min(rj);
+        }
+    }
+
+    let mut classes: Vec<Vec<usize>> = vec![];
+    let mut class_of_root = vec![usize::MAX; npts];
+    for i in 0..npts {
+        let r = root(&parent, i);
+        if class_of_root[r] == usize::MAX {
+            class_of_root[r] = classes.len();
+            classes.push(vec![]);
+        }
+        classes[class_of_root[r]].push(i);
+    }
+    classes
+}
+// 1f5b8a3c ends here
+
+// [[file:../gchemol-geometry.note::4e9c7a20][4e9c7a20]]
+#[test]
+fn test_detect_point_group() {
+    // water
+    let positions = [[0.0, 0.0, 0.1173], [0.0, 0.7572, -0.4692], [0.0, -0.7572, -0.4692]];
+    let pg = detect_point_group(&positions, &[8, 1, 1], 0.01).unwrap();
+    assert_eq!(pg.name, "C2v");
+    assert_eq!(pg.order(), 4);
+    assert_eq!(pg.symmetry_number(), 2);
+    assert_eq!(pg.classes, vec![vec![0], vec![1, 2]]);
+    assert_eq!(detect_point_group(&positions, &[8, 1, 2], 0.01).unwrap().name, "Cs");
+
+    // ammonia
+    let positions = [
+        [0.0, 0.0, 0.1162],
+        [0.0, 0.9397, -0.2711],
+        [0.8138, -0.4699, -0.2711],
+        [-0.8138, -0.4699, -0.2711],
+    ];
+    let pg = detect_point_group(&positions, &[7, 1, 1, 1], 0.01).unwrap();
+    assert_eq!(pg.name, "C3v");
+    assert_eq!(pg.symmetry_number(), 3);
+
+    // methane with a little noise
+    let positions = [
+        [0.0, 0.0, 0.0],
+        [0.629, 0.629, 0.6291],
+        [-0.629, -0.6289, 0.629],
+        [-0.629, 0.629, -0.629],
+        [0.6292, -0.629, -0.629],
+    ];
+    let pg = detect_point_group(&positions, &[6, 1, 1, 1, 1], 0.01).unwrap();
+    assert_eq!(pg.name, "Td");
+    assert_eq!(pg.order(), 24);
+    assert_eq!(pg.symmetry_number(), 12);
+
+    // octahedron and planar hexagon
+    let octahedron = [
+        [0.0, 0.0, 0.0],
+        [1.5, 0.0, 0.0],
+        [-1.5, 0.0, 0.0],
+        [0.0, 1.5, 0.0],
+        [0.0, -1.5, 0.0],
+        [0.0, 0.0, 1.5],
+        [0.0, 0.0, -1.5],
+    ];
+    assert_eq!(detect_point_group(&octahedron, &[16, 9, 9, 9, 9, 9, 9], 0.01).unwrap().name, "Oh");
+    let hexagon: Vec<_> = (0..6).map(|i| [(i as f64 * PI / 3.0).cos(), (i as f64 * PI / 3.0).sin(), 0.0]).collect();
+    let pg = detect_point_group(&hexagon, &[6; 6], 0.01).unwrap();
+    assert_eq!(pg.name, "D6h");
+    assert_eq!(pg.order(), 24);
+
+    // icosahedron
+    let g = (1.0 + 5f64.sqrt()) / 2.0;
+    let mut icosahedron = vec![];
+    for &a in &[-1.0, 1.0] {
+        for &b in &[-g, g] {
+            icosahedron.extend_from_slice(&[[0.0, a, b], [a, b, 0.0], [b, 0.0, a]]);
+        }
+    }
+    let pg = detect_point_group(&icosahedron, &[5; 12], 0.01).unwrap();
+    assert_eq!(pg.name, "Ih");
+    assert_eq!(pg.order(), 120);
+
+    // staggered ethane-like and allene-like structures
+    let ethane = [
+        [0.0, 0.0, 0.76],
+        [0.0, 0.0, -0.76],
+        [1.02, 0.0, 1.16],
+        [-0.51, 0.8834, 1.16],
+        [-0.51, -0.8834, 1.16],
+        [-1.02, 0.0, -1.16],
+        [0.51, 0.8834, -1.16],
+        [0.51, -0.8834, -1.16],
+    ];
+    assert_eq!(detect_point_group(&ethane, &[6, 6, 1, 1, 1, 1, 1, 1], 0.01).unwrap().name, "D3d");
+    let allene = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.31],
+        [0.0, 0.0, -1.31],
+        [0.0, 0.93, 1.87],
+        [0.0, -0.93, 1.87],
+        [0.93, 0.0, -1.87],
+        [-0.93, 0.0, -1.87],
+    ];
+    assert_eq!(detect_point_group(&allene, &[6, 6, 6, 1, 1, 1, 1], 0.01).unwrap().name, "D2d");
+
+    // linear structures
+    let co2 = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.16], [0.0, 0.0, -1.16]];
+    let pg = detect_point_group(&co2, &[6, 8, 8], 0.01).unwrap();
+    assert_eq!(pg.name, "D∞h");
+    assert_eq!(pg.symmetry_number(), 2);
+    let hcn = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.06], [0.0, 0.0, -1.15]];
+    assert_eq!(detect_point_group(&hcn, &[6, 1, 7], 0.01).unwrap().name, "C∞v");
+
+    // operations are exact for the found group
+    for op in &pg.operations {
+        let moved = op.transform.apply(&co2);
+        for (i, &j) in op.permutation.iter().enumerate() {
+            assert!(moved[i].vecdist(&co2[j]) < 1e-8);
+        }
+    }
+}
+// 4e9c7a20 ends here
+
+// [[file:../gchemol-geometry.note::a6d23f91][a6d23f91]]
+#[test]
+fn test_symmetrize() {
+    // methane with noise
+    let positions = [
+        [0.001, 0.0, 0.0],
+        [0.629, 0.629, 0.6291],
+        [-0.6295, -0.6289, 0.629],
+        [-0.629, 0.629, -0.629],
+        [0.6292, -0.629, -0.6287],
+    ];
+    let labels = [6, 1, 1, 1, 1];
+    assert_ne!(detect_point_group(&positions, &labels, 1e-4).unwrap().name, "Td");
+
+    let symmetrized = symmetrize(&positions, &labels, "Td", 0.01).unwrap();
+    assert_eq!(symmetrized.point_group, "Td");
+    assert!(symmetrized.max_displacement > 1e-4 && symmetrized.max_displacement < 2e-3);
+    let pg = detect_point_group(&symmetrized.positions, &labels, 1e-8).unwrap();
+    assert_eq!(pg.name, "Td");
+
+    // symmetrized positions are invariant under all operations
+    for op in &pg.operations {
+        let moved = op.transform.apply(&symmetrized.positions);
+        for (i, &j) in op.permutation.iter().enumerate() {
+            assert!(moved[i].vecdist(&symmetrized.positions[j]) < 1e-10);
+        }
+    }
+
+    // a tight tolerance keeps the broken symmetry
+    assert!(symmetrize(&positions, &labels, "Td", 1e-4).is_err());
+    assert!(symmetrize(&positions, &labels, "Oh", 0.01).is_err());
+
+    // symmetrize to a subgroup, keeping the broken symmetry of Td
+    let pg = detect_point_group(&positions, &labels, 0.01).unwrap();
+    assert_eq!(pg.subgroups("C3v").len(), 4);
+    assert_eq!(pg.subgroups("S4").len(), 3);
+    assert_eq!(pg.subgroups("Td").len(), 1);
+    let symmetrized_td = symmetrize(&positions, &labels, "Td", 0.01).unwrap();
+    let symmetrized = symmetrize(&positions, &labels, "C3v", 0.01).unwrap();
+    assert_eq!(symmetrized.point_group, "C3v");
+    assert!(symmetrized.max_displacement < symmetrized_td.max_displacement);
+    let pg = detect_point_group(&symmetrized.positions, &labels, 1e-8).unwrap();
+    assert_eq!(pg.name, "C3v");
+    assert_eq!(pg.classes, vec![vec![0], vec![1, 2, 3], vec![4]]);
+}
+// a6d23f91 ends here
@@ -93,6 +93,28 @@ pub trait GeometryCoord3SliceExt {
 
     /// Apply point inversion.
     fn point_invert(&mut self);
+
+    /// Return the inertia tensor with respect to the center of mass.
+    fn inertia_tensor(&self, masses: &[f64]) -> Matrix3f;
+
+    /// Return the principal moments of inertia in ascending order.
+    fn principal_moments(&self, masses: &[f64]) -> [f64; 3];
+
+    /// Return the principal axes of inertia, ordered as the principal
+    /// moments. The axes form a right-handed frame, and the sign of each axis
+    /// is chosen deterministically (see `orient_principal_axes`).
+    fn principal_axes(&self, masses: &[f64]) -> [Coord3; 3];
+
+    /// Move the center of mass to the origin, and rotate the structure so
+    /// that its principal axes align with the x, y and z axes in the order of
+    /// ascending principal moments.
+    ///
+    /// The sign of the first two axes is chosen to make the mass-weighted
+    /// third moment of coordinates along each axis positive, or else the
+    /// first point off the perpendicular plane to have a positive coordinate.
+    /// The third axis completes a right-handed frame. The orientation within
+    /// degenerate principal moments remains arbitrary.
+    fn orient_principal_axes(&mut self, masses: &[f64]);
 }
 
 impl GeometryCoord3SliceExt for [Coord3] {
@@ -123,6 +145,68 @@ impl GeometryCoord3SliceExt for [Coord3] {
             p[2] *= -1.0;
         }
     }
+
+    /// Return the inertia tensor with respect to the center of mass.
+    fn inertia_tensor(&self, masses: &[f64]) -> Matrix3f {
+        assert_eq!(self.len(), masses.len(), "masses size mismatch!");
+        let com = Vector3f::from(self.center_of_mass(masses));
+        let mut tensor = Matrix3f::zeros();
+        for (&p, &m) in self.iter().zip(masses) {
+            let r = Vector3f::from(p) - com;
+            tensor += m * (r.norm_squared() * Matrix3f::identity() - r * r.transpose());
+        }
+        tensor
+    }
+
+    /// Return the principal moments of inertia in ascending order.
+    fn principal_moments(&self, masses: &[f64]) -> [f64; 3] {
+        let mut moments: Vec<f64> = self.inertia_tensor(masses).symmetric_eigenvalues().iter().copied().collect();
+        moments.sort_by(|a, b| a.total_cmp(b));
+        [moments[0], moments[1], moments[2]]
+    }
+
+    /// Return the principal axes of inertia, ordered as the principal moments.
+    fn principal_axes(&self, masses: &[f64]) -> [Coord3; 3] {
+        let se = self.inertia_tensor(masses).symmetric_eigen();
+        let mut order = [0, 1, 2];
+        order.sort_by(|&i, &j| se.eigenvalues[i].total_cmp(&se.eigenvalues[j]));
+
+        let com = Vector3f::from(self.center_of_mass(masses));
+        let mut axes: Vec<Vector3f> = order.iter().map(|&i| se.eigenvectors.column(i).into_owned()).collect();
+        for axis in axes.iter_mut().take(2) {
+            if axis_sign(self, masses, com, axis) < 0.0 {
+                *axis = -*axis;
+            }
+        }
+        axes[2] = axes[0].cross(&axes[1]);
+        [axes[0].into(), axes[1].into(), axes[2].into()]
+    }
+
+    /// Reorient the structure into its principal-axes frame.
+    fn orient_principal_axes(&mut self, masses: &[f64]) {
+        let com = Vector3f::from(self.center_of_mass(masses));
+        let axes = self.principal_axes(masses);
+        let rot = Matrix3f::from_rows(&[
+            Vector3f::from(axes[0]).transpose(),
+            Vector3f::from(axes[1]).transpose(),
+            Vector3f::from(axes[2]).transpose(),
+        ]);
+        for p in self.iter_mut() {
+            *p = (rot * (Vector3f::from(*p) - com)).into();
+        }
+    }
+}
+
+/// Return the sign for orienting principal `axis` deterministically.
+fn axis_sign(points: &[Coord3], masses: &[f64], com: Vector3f, axis: &Vector3f) -> f64 {
+    const EPS: f64 = 1e-6;
+
+    let proj: Vec<f64> = points.iter().map(|&p| (Vector3f::from(p) - com).dot(axis)).collect();
+    let skew: f64 = proj.iter().zip(masses).map(|(x, m)| m * x.powi(3)).sum();
+    if skew.abs() > EPS {
+        return skew.signum();
+    }
+    proj.iter().find(|x| x.abs() > EPS).map_or(1.0, |x| x.signum())
 }
 // 86305981 ends here
 
@@ -144,4 +228,37 @@ fn test_point3_math() {
     assert_relative_eq!(p2.torsion(p3, p4, p5).to_degrees(), 70.529, epsilon = 1e-1);
     assert_relative_eq!(p1.torsion(p2, p3, p4).to_degrees(), -35.246, epsilon = 1e-1);
 }
+
+#[test]
+fn test_principal_axes() {
+    use approx::*;
+
+    // a distorted water molecule
+    let positions = vec![[0.0, 0.0, 0.1173], [0.0, 0.7572, -0.4692], [0.1, -0.7672, -0.4692]];
+    let masses = [15.999, 1.008, 1.008];
+
+    let moments = positions.principal_moments(&masses);
+    assert!(moments[0] <= moments[1] && moments[1] <= moments[2]);
+    let trace = positions.inertia_tensor(&masses).trace();
+    assert_relative_eq!(moments.iter().sum::<f64>(), trace, epsilon = 1e-8);
+
+    // the reoriented structure has a diagonal inertia tensor
+    let mut oriented = positions.clone();
+    oriented.orient_principal_axes(&masses);
+    let tensor = oriented.inertia_tensor(&masses);
+    assert_relative_eq!(tensor, Matrix3f::from_diagonal(&moments.into()), epsilon = 1e-8);
+    assert_relative_eq!(Vector3f::from(oriented.center_of_mass(&masses)), Vector3f::zeros(), epsilon = 1e-8);
+
+    // the same orientation regardless of initial rotation and translation
+    let t = crate::RigidTransform::from_axis_angle([0.7, 0.1, -1.0], 1.3) * crate::RigidTransform::from_translation([-0.4, 2.2, 0.9]);
+    let mut rotated = t.apply(&positions);
+    rotated.orient_principal_axes(&masses);
+    for (p, q) in rotated.iter().zip(&oriented) {
+        assert_relative_eq!(Vector3f::from(*p), Vector3f::from(*q), epsilon = 1e-8);
+    }
+
+    let axes = positions.principal_axes(&masses);
+    let det = Matrix3f::from_columns(&[axes[0].into(), axes[1].into(), axes[2].into()]).determinant();
+    assert_relative_eq!(det, 1.0, epsilon = 1e-8);
+}
 // fb603613 ends here
@@ -3,7 +3,6 @@ use super::*;
 use crate::base::euclidean_distance;
 use vecfx::*;
 
-use na::{Rotation3, Vector3};
 use nalgebra as na;
 // 7ba19d02 ends here
 
@@ -12,11 +11,7 @@ type Points = Vec<Coord3>;
 
 /// Translate all points to a new location
 pub fn translate(points: &mut Points, loc: Coord3) {
-    for i in 0..points.len() {
-        for v in 0..3 {
-            points[i][v] += loc[v];
-        }
-    }
+    *points = RigidTransform::from_translation(loc).apply(points);
 }
 
 /// Return all distances between any pair of points
@@ -39,18 +34,12 @@ pub fn get_distance_matrix(points: &[Coord3]) -> Vec<Vec<f64>> {
 
 /// rotate coordinates about x axis in radian
 pub fn rotate_about_x_axis(points: &Points, angle: f64, center: Coord3) -> Points {
-    let axis = Vector3::x_axis();
-    let r = Rotation3::from_axis_angle(&axis, angle);
-
-    let mut rpoints = vec![];
-    let center = Vector3::from(center);
-    for &p in points.iter() {
-        let v = Vector3::from(p) - center;
-        let t: Coord3 = (r * v + center).into();
-        rpoints.push(t);
-    }
+    let [x, y, z] = center;
+    let t = RigidTransform::from_translation(center)
+        * RigidTransform::from_axis_angle([1.0, 0.0, 0.0], angle)
+        * RigidTransform::from_translation([-x, -y, -z]);
 
-    rpoints
+    t.apply(points)
 }
 
 /// Return mirror inverted structure